@@ -0,0 +1,94 @@
+// The serde_derive this crate uses puts its impls inside constants, which newer compilers warn about.
+#![allow(non_local_definitions)]
+
+use std::fmt;
+use std::io::{Read, Write};
+use serde_cbor;
+use lex;
+use parse::{label_definition, Assembly};
+
+/// Where an instruction of a program came from in its source.
+///
+/// Lines and columns are counted from zero, as they are in `Diagnostic`s; they are shown counted
+/// from one.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SourceLocation {
+    /// The file the instruction is in.
+    pub file: String,
+    /// The line of the file. For instructions that came from a macro, this is the line the macro
+    /// was invoked on.
+    pub line: u64,
+    /// The column the instruction's statement (or macro invocation) starts at.
+    pub column: u64,
+    /// The last label at or before the instruction, if there is one.
+    pub label: Option<String>,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line + 1, self.column + 1)?;
+        match self.label {
+            Some(ref label) => write!(f, " (in {})", label),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Debug information for an assembled program: where in the source each of its instructions came
+/// from, so that a program counter (like the one MLeM faulted at) can be traced back to the source.
+///
+/// ```
+/// use mlem_asm::*;
+/// let source = "move 3 R0\nloop: sub R0 1\n  jnz loop R0\nhalt";
+/// let assembly = assemble_source(source, "count.asm", &Options::default()).unwrap();
+/// let info = DebugInfo::new(&assembly);
+/// let location = info.lookup(2).unwrap();
+/// assert_eq!((location.line, location.column), (2, 2));
+/// assert_eq!(location.to_string(), "count.asm:3:3 (in loop)");
+/// assert_eq!(info.lookup(0).unwrap().label, None);
+/// assert_eq!(info.lookup(4), None);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DebugInfo {
+    /// Where each instruction came from, by its index in the program.
+    pub instructions: Vec<SourceLocation>,
+}
+
+impl DebugInfo {
+    /// Collect the debug information of an assembled program.
+    pub fn new(assembly: &Assembly) -> Self {
+        let mut instructions = Vec::with_capacity(assembly.program.len());
+        let mut label = None;
+        for line in &assembly.lines {
+            // Of several labels on the same line, the first one is used.
+            if let Some(name) = lex::lex_line(&line.text).first().and_then(|t| label_definition(t)) {
+                label = Some(name.to_string());
+            }
+            for _ in line.instructions.clone() {
+                instructions.push(SourceLocation {
+                    file: line.file.clone(),
+                    line: line.line,
+                    column: line.columns.start as u64,
+                    label: label.clone(),
+                });
+            }
+        }
+        DebugInfo { instructions }
+    }
+
+    /// Find where the instruction at `pc` came from, if it is in the program.
+    pub fn lookup(&self, pc: u64) -> Option<&SourceLocation> {
+        self.instructions.get(pc as usize)
+    }
+}
+
+/// Writes the debug information of a program to a writer in self-describing CBOR, as
+/// `program_to_writer` writes the program itself.
+pub fn debug_info_to_writer(info: &DebugInfo, mut w: &mut dyn Write) -> Result<(), serde_cbor::Error> {
+    serde_cbor::ser::to_writer_sd(&mut w, info)
+}
+
+/// Reads the debug information of a program from a reader, as `debug_info_to_writer` writes it.
+pub fn debug_info_from_reader(r: &mut dyn Read) -> Result<DebugInfo, serde_cbor::Error> {
+    serde_cbor::de::from_reader(r)
+}
@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use serde_cbor;
+use mlem::{Address, Instruction, Program};
+use format::{format_instruction, writes_literal, FormatOptions};
+use parse::jump_target;
+
+/// Reads an assembled program from a reader, in the packed, self-describing CBOR that
+/// `program_to_writer` writes.
+pub fn program_from_reader(r: &mut dyn Read) -> Result<Program, serde_cbor::Error> {
+    serde_cbor::de::from_reader(r)
+}
+
+/// Check whether some bytes look like an assembled program: that is, whether they start with the
+/// tag that marks self-describing CBOR, as `program_to_writer` writes.
+///
+/// ```
+/// use mlem_asm::*;
+/// let mut bytes = Vec::new();
+/// program_to_writer(&vec![Instruction::Halt], &mut bytes).unwrap();
+/// assert!(is_assembled_program(&bytes));
+/// assert!(!is_assembled_program(b"halt\n"));
+/// ```
+pub fn is_assembled_program(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xd9, 0xd9, 0xf7])
+}
+
+/// An instruction that can't be written in mlasm, because it writes to a literal. (Such an
+/// instruction faults if it is ever executed.)
+#[derive(Debug, PartialEq, Clone)]
+pub struct DisassemblyError {
+    /// The index of the instruction in the program.
+    pub index: usize,
+    pub instruction: Instruction,
+}
+
+impl fmt::Display for DisassemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Instruction {} ({:?}) writes to a literal, which can't be written in mlasm.", self.index, self.instruction)
+    }
+}
+
+impl Error for DisassemblyError {}
+
+/// Write a program as mlasm, one instruction per line, in the canonical form `format_instruction`
+/// writes, with the given options. Assembling the result gives back the same program.
+/// If `numbered` is true, each line is followed by a comment giving the index of its instruction.
+///
+/// ```
+/// use mlem_asm::*;
+/// use mlem_asm::format::FormatOptions;
+/// let program = vec![
+///     Instruction::Move(Address::Literal(5), Address::RegAbs(Register::SP)),
+///     Instruction::JumpNotZero(Address::Literal(0), Address::MemReg(Register::R1)),
+///     Instruction::Halt,
+/// ];
+/// let text = disassemble(&program, &FormatOptions::default(), true).unwrap();
+/// assert_eq!(text, "move 5 RSP  ; 0\njnz 0 *R1   ; 1\nhalt        ; 2\n");
+/// assert_eq!(parse_program(&text), Ok(program));
+/// ```
+///
+/// Jumps to literal targets outside the program can't be assembled by default, so if there are
+/// any, the text starts with a directive to allow them.
+pub fn disassemble(program: &Program, options: &FormatOptions, numbered: bool) -> Result<String, DisassemblyError> {
+    let mut lines = Vec::new();
+    for (index, instruction) in program.iter().enumerate() {
+        if writes_literal(instruction) {
+            return Err(DisassemblyError { index, instruction: *instruction });
+        }
+        lines.push(format_instruction(instruction, options));
+    }
+    let width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+    let mut text = String::new();
+    let out_of_range = |a: &Address| match *a { Address::Literal(t) => t >= program.len() as u64, _ => false };
+    if program.iter().any(|i| jump_target(i).as_ref().is_some_and(&out_of_range)) {
+        text += ".allow jump-out-of-range\n";
+    }
+    for (index, line) in lines.iter().enumerate() {
+        if numbered {
+            text += &format!("{:<width$} ; {}\n", line, index, width = width + 1);
+        } else {
+            text += &format!("{}\n", line);
+        }
+    }
+    Ok(text)
+}
@@ -0,0 +1,370 @@
+use mlem::{Address, Instruction, Register};
+use lex;
+use parse::{canonical_instruction_name, parse_register_name};
+use syntax::{LineNode, SyntaxTree};
+
+/// The case to write register names in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Case {
+    Upper,
+    Lower,
+}
+
+/// The base to write literals (and absolute memory addresses) in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Radix {
+    Decimal,
+    /// With a `0x` prefix.
+    Hexadecimal,
+    /// With a `0b` prefix.
+    Binary,
+}
+
+/// Settings that control how instructions and addresses are written.
+/// The default is uppercase registers and decimal literals: `move 16 R0`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FormatOptions {
+    pub register_case: Case,
+    pub radix: Radix,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions { register_case: Case::Upper, radix: Radix::Decimal }
+    }
+}
+
+/// Write an instruction as mlasm, in canonical form: the lowercase name of the instruction, then
+/// its operands, separated by single spaces.
+///
+/// Parsing the result gives back the same instruction, unless it writes to a literal (see
+/// `writes_literal`), which mlasm doesn't allow.
+///
+/// ```
+/// use mlem_asm::*;
+/// use mlem_asm::format::{format_instruction, FormatOptions, Case, Radix};
+/// let instruction = Instruction::Move(Address::MemReg(Register::R0), Address::RegAbs(Register::R1));
+/// assert_eq!(format_instruction(&instruction, &FormatOptions::default()), "move *R0 R1");
+///
+/// let options = FormatOptions { register_case: Case::Lower, radix: Radix::Hexadecimal };
+/// let instruction = Instruction::JumpNotZero(Address::Literal(2), Address::RegAbs(Register::R7));
+/// assert_eq!(format_instruction(&instruction, &options), "jnz 0x2 r7");
+/// assert_eq!(parse::parse_line("jnz 0x2 r7"), Ok(Some(instruction)));
+/// ```
+pub fn format_instruction(instruction: &Instruction, options: &FormatOptions) -> String {
+    let (name, operands) = match *instruction {
+        Instruction::NoOp => ("noop", vec![]),
+        Instruction::Zero(a) => ("zero", vec![a]),
+        Instruction::Move(a, b) => ("move", vec![a, b]),
+        Instruction::Output(a) => ("output", vec![a]),
+        Instruction::Input(a) => ("input", vec![a]),
+        Instruction::Add(a, b) => ("add", vec![a, b]),
+        Instruction::Sub(a, b) => ("sub", vec![a, b]),
+        Instruction::Jump(a) => ("jump", vec![a]),
+        Instruction::JumpIfZero(a, b) => ("jz", vec![a, b]),
+        Instruction::JumpNotZero(a, b) => ("jnz", vec![a, b]),
+        Instruction::Push(a) => ("push", vec![a]),
+        Instruction::Pop(a) => ("pop", vec![a]),
+        Instruction::Halt => ("halt", vec![]),
+        Instruction::Illegal => ("illegal", vec![]),
+    };
+    operands.iter().fold(name.to_string(), |text, a| format!("{} {}", text, format_address(a, options)))
+}
+
+/// Write an address as mlasm: a register (`R0`), a memory address (`*16`), a memory address in a
+/// register (`*R0`), or a literal (`16`).
+pub fn format_address(address: &Address, options: &FormatOptions) -> String {
+    match *address {
+        Address::RegAbs(r) => format_register(r, options),
+        Address::MemAbs(a) => format!("*{}", format_literal(a, options)),
+        Address::MemReg(r) => format!("*{}", format_register(r, options)),
+        Address::Literal(v) => format_literal(v, options),
+    }
+}
+
+/// Write the name of a register.
+pub fn format_register(register: Register, options: &FormatOptions) -> String {
+    let name = match register {
+        Register::R0 => "R0", Register::R1 => "R1", Register::R2 => "R2", Register::R3 => "R3",
+        Register::R4 => "R4", Register::R5 => "R5", Register::R6 => "R6", Register::R7 => "R7",
+        Register::SP => "RSP", Register::BP => "RBP",
+    };
+    match options.register_case {
+        Case::Upper => name.to_string(),
+        Case::Lower => name.to_lowercase(),
+    }
+}
+
+/// Write a literal number in the radix given by the options.
+pub fn format_literal(value: u64, options: &FormatOptions) -> String {
+    match options.radix {
+        Radix::Decimal => value.to_string(),
+        Radix::Hexadecimal => format!("{:#x}", value),
+        Radix::Binary => format!("{:#b}", value),
+    }
+}
+
+/// Check whether an instruction writes to a literal. Such an instruction faults if it is ever
+/// executed, and can't be written in mlasm.
+pub fn writes_literal(instruction: &Instruction) -> bool {
+    matches!(*instruction,
+        Instruction::Zero(Address::Literal(_)) | Instruction::Input(Address::Literal(_)) |
+        Instruction::Pop(Address::Literal(_)) | Instruction::Move(_, Address::Literal(_)) |
+        Instruction::Add(Address::Literal(_), _) | Instruction::Sub(Address::Literal(_), _))
+}
+
+/// A line of source, split into the parts the source formatter lays out.
+struct SourceLayout<'a> {
+    /// The label definitions at the start of the line, with their colons.
+    labels: Vec<&'a str>,
+    /// The statement on the line (an instruction, directive, or macro invocation), normalized.
+    pieces: Vec<String>,
+    /// The text of the comment on the line, after the semicolon.
+    comment: Option<&'a str>,
+    /// Whether the line started with whitespace.
+    indented: bool,
+    /// Text after the end of the line as the lexer sees it (a carriage return in the middle of the
+    /// line ends it), if there is any.
+    rest: &'a str,
+    /// The whole line, without its line ending, if it has an unclosed parenthesis or quote. What
+    /// those take in isn't split into pieces, so the line is kept as it is.
+    verbatim: Option<String>,
+}
+
+impl<'a> SourceLayout<'a> {
+    fn new(line: &LineNode<'a>, options: &FormatOptions) -> Self {
+        let text = line.text();
+        let text = &text[..text.len() - line.newline.len()];
+        if lex::find_unclosed(text).is_some() {
+            let verbatim = Some(text.to_string());
+            return SourceLayout { labels: Vec::new(), pieces: Vec::new(), comment: None, indented: false, rest: "", verbatim };
+        }
+        let labels = line.labels.iter().map(|t| t.text).collect();
+        let statement: Vec<&str> = line.statement.iter().map(|t| t.text).collect();
+        let pieces = normalize_statement(&statement, options);
+        // A semicolon with nothing after it just ends the statement.
+        let code = !line.labels.is_empty() || !line.statement.is_empty();
+        let comment = line.comment.map(|c| &c.text[1..]).filter(|c| !c.trim().is_empty() || !code);
+        let indented = line.tokens().first().is_some_and(|t| !t.leading.is_empty());
+        let rest = if line.trailing.trim().is_empty() { "" } else { line.trailing.trim_matches([' ', '\t']) };
+        SourceLayout { labels, pieces, comment, indented, rest, verbatim: None }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.labels.is_empty() && self.pieces.is_empty() && self.comment.is_none() && self.rest.is_empty() && self.verbatim.is_none()
+    }
+
+    /// The label definitions, as they are written.
+    fn label_text(&self) -> String {
+        self.labels.join(" ")
+    }
+}
+
+/// Write a statement in canonical form: instruction names (including aliases) as their canonical
+/// lowercase names, directive names in lowercase, and register operands in the case given by
+/// the options. Everything else is kept as it is.
+fn normalize_statement(pieces: &[&str], options: &FormatOptions) -> Vec<String> {
+    let first = match pieces.first() {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+    if first.starts_with('.') {
+        return Some(first.to_lowercase()).into_iter().chain(pieces[1..].iter().map(|p| p.to_string())).collect();
+    }
+    let name = canonical_instruction_name(first).map_or(first.to_string(), |n| n.to_string());
+    let operands = pieces[1..].iter().map(|operand| {
+        let (star, register) = match operand.strip_prefix('*') {
+            Some(register) => ("*", register),
+            None => ("", *operand),
+        };
+        match parse_register_name(register) {
+            Ok(r) => format!("{}{}", star, format_register(r, options)),
+            Err(_) => operand.to_string(),
+        }
+    });
+    Some(name).into_iter().chain(operands).collect()
+}
+
+/// Format the source of a program: instruction names, aliases, directive names and registers are
+/// normalized as `format_instruction` writes them (with the register case given by the options),
+/// and the parts of each line are lined up in columns. Comments and blank lines are kept.
+///
+/// Labels start each line, and statements start in a column after the longest label. In each block
+/// of lines not separated by blank lines, the pieces of statements are lined up in columns, and
+/// so are the comments after them. Literals, expressions, and other operands are left as they are,
+/// so the formatted source assembles to the same program. Lines with an unclosed parenthesis or
+/// quote are kept as they are. Every line ends as the first line of the source does, with `\n` or `\r\n`.
+///
+/// ```
+/// use mlem_asm::format::{format_source, FormatOptions};
+/// let source = "start: MOVE 1 r0 ; one\n  usub R0 1\n\tJNZ start r0;\n";
+/// assert_eq!(format_source(source, &FormatOptions::default()), "\
+/// start:  move 1     R0 ; one
+///         sub  R0    1
+///         jnz  start R0
+/// ");
+/// ```
+pub fn format_source(source: &str, options: &FormatOptions) -> String {
+    let tree = SyntaxTree::new(source);
+    let newline = tree.lines.first().map_or("\n", |l| if l.newline.is_empty() { "\n" } else { l.newline });
+    let lines: Vec<SourceLayout> = tree.lines.iter().map(|l| SourceLayout::new(l, options)).collect();
+    // Statements start at the first multiple of four columns after the longest labels.
+    let indent = lines.iter().filter(|l| !l.labels.is_empty()).map(|l| (l.label_text().chars().count() + 4) / 4 * 4).max().unwrap_or(0);
+    let mut formatted = Vec::new();
+    for block in lines.split(|l| l.is_blank()) {
+        let mut widths: Vec<usize> = Vec::new();
+        for line in block {
+            for (i, piece) in line.pieces.iter().enumerate() {
+                let width = piece.chars().count();
+                if i < widths.len() { widths[i] = widths[i].max(width); } else { widths.push(width); }
+            }
+        }
+        let code: Vec<String> = block.iter().map(|line| {
+            let mut text = line.label_text();
+            if !line.pieces.is_empty() {
+                text += &" ".repeat(indent - text.chars().count());
+            }
+            for (i, piece) in line.pieces.iter().enumerate() {
+                text += piece;
+                if i + 1 < line.pieces.len() {
+                    text += &" ".repeat(widths[i] + 1 - piece.chars().count());
+                }
+            }
+            text
+        }).collect();
+        // Comments after code line up one column after the longest code with a comment after it.
+        let comment_column = block.iter().zip(&code)
+            .filter(|&(l, c)| l.comment.is_some() && !c.is_empty())
+            .map(|(_, c)| c.chars().count() + 1).max().unwrap_or(0);
+        for (line, code) in block.iter().zip(code) {
+            let text = match line.comment {
+                Some(comment) if code.is_empty() => {
+                    let indent = if line.indented { " ".repeat(indent) } else { String::new() };
+                    format!("{};{}", indent, comment.trim_end())
+                }
+                Some(comment) => {
+                    let padding = " ".repeat(comment_column - code.chars().count());
+                    format!("{}{};{}", code, padding, comment.trim_end())
+                }
+                None => code,
+            };
+            formatted.push(match line.verbatim {
+                Some(ref verbatim) => verbatim.clone(),
+                None => text + line.rest,
+            });
+        }
+        formatted.push(String::new());
+    }
+    // Blocks are separated by the blank lines they were split at; there are none after the last.
+    while formatted.last().is_some_and(|l| l.is_empty()) {
+        formatted.pop();
+    }
+    formatted.iter().map(|l| format!("{}{}", l, newline)).collect()
+}
+
+#[cfg(test)]
+mod test_format {
+    use super::*;
+    use parse::{parse_line, parse_program};
+
+    const REGISTERS: [Register; 10] = [
+        Register::R0, Register::R1, Register::R2, Register::R3, Register::R4,
+        Register::R5, Register::R6, Register::R7, Register::SP, Register::BP,
+    ];
+
+    const VALUES: [u64; 8] = [0, 1, 2, 9, 10, 255, 0x8000, u64::MAX];
+
+    /// Every option setting.
+    fn all_options() -> Vec<FormatOptions> {
+        let mut options = Vec::new();
+        for &register_case in &[Case::Upper, Case::Lower] {
+            for &radix in &[Radix::Decimal, Radix::Hexadecimal, Radix::Binary] {
+                options.push(FormatOptions { register_case, radix });
+            }
+        }
+        options
+    }
+
+    /// Every kind of address, with every register and a range of values.
+    fn all_addresses() -> Vec<Address> {
+        let mut addresses = Vec::new();
+        for &r in &REGISTERS {
+            addresses.push(Address::RegAbs(r));
+            addresses.push(Address::MemReg(r));
+        }
+        for &v in &VALUES {
+            addresses.push(Address::MemAbs(v));
+            addresses.push(Address::Literal(v));
+        }
+        addresses
+    }
+
+    /// Every instruction, with every combination of the given addresses as operands.
+    fn all_instructions(addresses: &[Address]) -> Vec<Instruction> {
+        let mut instructions = vec![Instruction::NoOp, Instruction::Halt, Instruction::Illegal];
+        let unary: [fn(Address) -> Instruction; 6] = [
+            Instruction::Zero, Instruction::Output, Instruction::Input, Instruction::Jump, Instruction::Push, Instruction::Pop,
+        ];
+        let binary: [fn(Address, Address) -> Instruction; 5] = [
+            Instruction::Move, Instruction::Add, Instruction::Sub, Instruction::JumpIfZero, Instruction::JumpNotZero,
+        ];
+        for &a in addresses {
+            instructions.extend(unary.iter().map(|f| f(a)));
+            for &b in addresses {
+                instructions.extend(binary.iter().map(|f| f(a, b)));
+            }
+        }
+        instructions
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let instructions = all_instructions(&all_addresses());
+        for options in all_options() {
+            for instruction in instructions.iter().filter(|i| !writes_literal(i)) {
+                let text = format_instruction(instruction, &options);
+                assert_eq!(parse_line(&text), Ok(Some(*instruction)), "{} ({:?})", text, options);
+            }
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let options = FormatOptions::default();
+        assert_eq!(format_instruction(&Instruction::Halt, &options), "halt");
+        assert_eq!(format_instruction(&Instruction::Pop(Address::RegAbs(Register::BP)), &options), "pop RBP");
+        let options = FormatOptions { register_case: Case::Lower, radix: Radix::Binary };
+        assert_eq!(format_instruction(&Instruction::Sub(Address::MemAbs(5), Address::MemReg(Register::SP)), &options), "sub *0b101 *rsp");
+        assert_eq!(format_address(&Address::Literal(0), &options), "0b0");
+        assert_eq!(format_literal(255, &FormatOptions { radix: Radix::Hexadecimal, ..options }), "0xff");
+    }
+
+    #[test]
+    fn test_format_source() {
+        let source = include_str!("../test.asm");
+        let formatted = format_source(source, &FormatOptions::default());
+        assert_eq!(formatted.lines().nth(4), Some("loop:   add    R0   1  ; Increment the value to output"));
+        // The example program is shipped formatted.
+        assert_eq!(formatted, source);
+
+        let source = "\n; header\n.EQU  TEN 10\n  ; indented\nlong_label: Subtract r0 TEN ;\n\n\n\
+                      .macro twice x\n loop\\@: oput *\\x ; print it\n.endm\n\ntwice rsp\n\tIllg\n\n";
+        let options = FormatOptions { register_case: Case::Lower, ..FormatOptions::default() };
+        let formatted = format_source(source, &options);
+        assert_eq!(formatted, "\n; header\n            .equ TEN 10\n            ; indented\nlong_label: sub  r0  TEN\n\n\n\
+                               \x20           .macro twice x\nloop\\@:     output *\\x ; print it\n            .endm\n\n\
+                               \x20           twice   rsp\n            illegal\n");
+        assert_eq!(format_source(&formatted, &options), formatted);
+        assert_eq!(parse_program(&formatted), parse_program(source));
+
+        // Line endings are kept, as is text after a carriage return in the middle of a line.
+        let source = "Noop\r\n  halt ;done\r\n";
+        assert_eq!(format_source(source, &FormatOptions::default()), "noop\r\nhalt ;done\r\n");
+        let source = "noop\r; after\n  HALT  \r  more \n";
+        assert_eq!(format_source(source, &FormatOptions::default()), "noop\r; after\nhalt\r  more\n");
+
+        // Lines with something unclosed aren't split up as usual, so they are left alone.
+        let source = "MOVE 1 r0\nmove (1 + 2 R0 ; z  \nprint \"abc  \n  halt\n";
+        assert_eq!(format_source(source, &FormatOptions::default()), "move 1 R0\nmove (1 + 2 R0 ; z  \nprint \"abc  \nhalt\n");
+    }
+}
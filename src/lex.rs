@@ -7,57 +7,95 @@ enum LexState {
     Done,
 }
 
-// Lex a line of characters into tokens
+// Lex a line of characters into tokens.
+// Whitespace inside parentheses doesn't split tokens, so `(BASE + 4)` is a single token.
+// Nothing inside single or double quotes splits tokens or starts a comment, so `';'` and
+// `"a string; with spaces"` are single tokens; a backslash inside quotes escapes the next character.
 pub fn lex_line(line: &str) -> Vec<&str> {
+    lex_line_with_comment(line).0
+}
+
+// Lex a line as `lex_line` does, also returning the text of its comment (everything after the
+// semicolon that starts it), if it has one.
+pub fn lex_line_with_comment(line: &str) -> (Vec<&str>, Option<&str>) {
+    let (tokens, comment, _) = lex_line_fully(line);
+    (tokens, comment)
+}
+
+// Find the first parenthesis or quote in a line that is never closed, if there is one, as the
+// column it's at and the character that opens it. An unclosed quote takes in the rest of the line,
+// comment and all, and an unclosed parenthesis everything up to the comment.
+pub fn find_unclosed(line: &str) -> Option<(usize, char)> {
+    lex_line_fully(line).2
+}
+
+// Lex a line as `lex_line_with_comment` does, also finding what it leaves unclosed, as `find_unclosed` does.
+fn lex_line_fully(line: &str) -> (Vec<&str>, Option<&str>, Option<(usize, char)>) {
     let mut v = Vec::new();
     // keep track of the start of the current token
     let mut start_index = 0;
-    // keep track of the end of the current token
-    let mut stop_index = 0;
     // the state of the lexer
     let mut state = LexState::Seperator;
+    // where the parentheses open in the current token are
+    let mut open: Vec<usize> = Vec::new();
+    // the quote character we're inside of and where it is, if any, and whether the last character was a backslash
+    let mut quote: Option<(usize, char)> = None;
+    let mut escaped = false;
+    let mut comment = None;
 
-    for character in line.chars() {
+    for (index, character) in line.char_indices() {
+        if let Some((_, q)) = quote {
+            // Quoted characters are always part of the current token.
+            if escaped { escaped = false; }
+            else if character == '\\' { escaped = true; }
+            else if character == q { quote = None; }
+            continue;
+        }
         match character {
             ';' | '\n' | '\r' => {
                 // A comment is starting or the line is ending. End the current token and stop lexing the line.
-                if state == LexState::Token { v.push(&line[start_index..stop_index]); }
-                state == LexState::Done;
+                if state == LexState::Token { v.push(&line[start_index..index]); }
+                if character == ';' {
+                    let end = line[index..].find(['\n', '\r']).map_or(line.len(), |e| index + e);
+                    comment = Some(&line[index + 1..end]);
+                }
+                state = LexState::Done;
                 break;
             }
-            ' ' | '\t' => { 
+            ' ' | '\t' if open.is_empty() => { 
                 // The current token is ending, or we'er in a long seperator.
                 // End the token and set the state, and proceed.
-                if state == LexState::Token { v.push(&line[start_index..stop_index]); }
+                if state == LexState::Token { v.push(&line[start_index..index]); }
                 state = LexState::Seperator;
             }
             _ => {
                 // Something else; a token.
                 // Reset the window and set the state.
-                if state == LexState::Seperator { start_index = stop_index; }
+                if state == LexState::Seperator { start_index = index; }
                 state = LexState::Token;
+                match character {
+                    '\'' | '"' => { quote = Some((index, character)); }
+                    '(' => { open.push(index); }
+                    ')' => { open.pop(); }
+                    _ => {}
+                }
             }
         }
-        // Advance the window's end index
-        stop_index += 1;
     }
     // Input is over; potentially end a token
-    // This is kind of a hack, to prevent double ending tokens.
     if state == LexState::Token {
-        let new_token = &line[start_index..stop_index];
-        // If there's nothing, there's no possibility for a duplicate.
-        if v.len() == 0 { v.push(new_token); }
-        // Check for duplicates
-        else {
-            let last_token = v.pop().unwrap();
-            // If they're not the same, put it back
-            if new_token != last_token { v.push(last_token); }
-            // No matter what, put the new one in. That way, if they're the same,
-            // there will only be one.
-            v.push(new_token); 
-        }
+        v.push(&line[start_index..]);
     }
-    return v;
+    let unclosed = quote.or(open.first().map(|&index| (index, '(')));
+    (v, comment, unclosed)
+}
+
+// Find the column a token returned by `lex_line` starts at in its line. Tokens are slices
+// of the line, so this is just the distance between them; for anything else, it's 0.
+pub fn offset(line: &str, token: &str) -> usize {
+    let start = line.as_ptr() as usize;
+    let position = token.as_ptr() as usize;
+    if position >= start && position + token.len() <= start + line.len() { position - start } else { 0 }
 }
 
 pub fn lex(source: &str) -> Vec<Vec<&str>> {
@@ -65,7 +103,7 @@ pub fn lex(source: &str) -> Vec<Vec<&str>> {
     for line in source.lines() {
         v.push(lex_line(line));
     };
-    return v;
+    v
 }
 
 #[cfg(test)]
@@ -83,6 +121,48 @@ mod test_lex {
         assert_eq!(&result[..], ["ident1", "ident2"]);
     }
 
+    #[test]
+    fn test_lex_line_keeping_comment() {
+        let (tokens, comment) = lex_line_with_comment("move ';' R0 ; a comment; with more\nnext");
+        assert_eq!((&tokens[..], comment), (&["move", "';'", "R0"][..], Some(" a comment; with more")));
+        assert_eq!(lex_line_with_comment("noop;"), (vec!["noop"], Some("")));
+        assert_eq!(lex_line_with_comment("  halt  "), (vec!["halt"], None));
+    }
+
+    #[test]
+    fn test_lex_line_with_parentheses() {
+        let result = lex_line("move (BASE + 4*2) R0 ; (not an expression");
+        assert_eq!(&result[..], ["move", "(BASE + 4*2)", "R0"]);
+    }
+
+    #[test]
+    fn test_lex_line_with_quotes() {
+        let result = lex_line("move ';' R0 ; comment");
+        assert_eq!(&result[..], ["move", "';'", "R0"]);
+        let result = lex_line("move '\\'' ' ' ' ; unterminated");
+        assert_eq!(&result[..], ["move", "'\\''", "' '", "' ; unterminated"]);
+        let result = lex_line("print \"Hello; \\\"world\\\"\" R0 ; comment");
+        assert_eq!(&result[..], ["print", "\"Hello; \\\"world\\\"\"", "R0"]);
+    }
+
+    #[test]
+    fn test_find_unclosed() {
+        assert_eq!(find_unclosed("move (1 + (2)) R0 ; (not an expression"), None);
+        assert_eq!(find_unclosed("move *( R0"), Some((6, '(')));
+        assert_eq!(find_unclosed("move ((1) R0 ; comment)"), Some((5, '(')));
+        assert_eq!(find_unclosed("print \"Hello R0 ; comment"), Some((6, '"')));
+        assert_eq!(find_unclosed("move ')' R0"), None);
+    }
+
+    #[test]
+    fn test_offset() {
+        let line = "  move R0\t*R1 ; comment";
+        let tokens = lex_line(line);
+        let offsets: Vec<usize> = tokens.iter().map(|t| offset(line, t)).collect();
+        assert_eq!(offsets, [2, 7, 10]);
+        assert_eq!(offset(line, "move"), 0);
+    }
+
     #[test]
     fn test_lex_multiple_lines() {
         let result = lex("l1i1 l1i2 ; line 1 comment\nl2i1 l2i2 l2i3 ; line 2 comment");
@@ -15,6 +15,35 @@
 //! move r0 r1 ; Set R1 equal to R0
 //! input *r0 ; read input into memory pointed at by r0
 //! output *0b01101 ; write output from memory pointed at by 0b01101
+//! ; Literals can only be read, so they can't be the destination of move, zero, input, pop, add or sub.
+//! ; Labels mark instructions, and can be used wherever a literal can:
+//! loop: jnz loop r0
+//! ; Constants are defined with .equ (or .define), before they are used:
+//! .equ BUFFER 0x10
+//! move r0 *BUFFER
+//! ; Constant expressions are folded when the program is assembled:
+//! move (BUFFER + 4*2) r1
+//! jump loop-1
+//! ; Literal jump targets must be inside the program; targets in registers or memory get a warning:
+//! jump r5
+//! ; Character literals stand for their character codes:
+//! move '\n' r2
+//! ; print expands to a move and an output for each character, using the given scratch register:
+//! print "Hello, world\n" r3
+//! ; Macros take parameters, and can define labels local to each expansion with \@:
+//! .macro countdown register
+//! loop\@: sub \register 1
+//!     jnz loop\@ \register
+//! .endm
+//! countdown r4
+//! ; Other files can be included, relative to this one or to the include paths in Options:
+//! .include "macros.asm"
+//! ; Conditional assembly chooses lines based on constants, including those defined in Options:
+//! .ifdef DEBUG
+//! output r0
+//! .endif
+//! ; Lints, which look for likely mistakes, can be allowed, made warnings, or denied (made errors):
+//! .allow unused-label
 //! ```
 //! 
 //! # Examples
@@ -42,23 +71,38 @@
 //! ```
 
 extern crate serde_cbor;
+#[macro_use]
+extern crate serde_derive;
 
 extern crate mlem;
 pub use mlem::{Address, Instruction, Register, Program};
 
 #[cfg(test)]
 mod test;
+#[cfg(test)]
+mod temp_dir;
 
 pub mod parse;
-pub use parse::{parse_program};
+pub use parse::{parse_program, parse_source, parse_file, assemble_source, assemble_file, Assembly, AssembledLine, Options, Diagnostic, ErrorKind, Severity,
+                Lint, Level, LINTS};
 pub mod lex;
+pub mod format;
+pub mod syntax;
+mod listing;
+pub use listing::listing_to_writer;
+mod disassemble;
+pub use disassemble::{disassemble, is_assembled_program, program_from_reader, DisassemblyError};
+mod run;
+pub use run::{run_program, RunOptions, RunOutcome};
+mod debug;
+pub use debug::{debug_info_from_reader, debug_info_to_writer, DebugInfo, SourceLocation};
 
 use std::io::Write;
 /// Writes an assembled program to a writer in packed, self-describing CBOR (a format MLeM can natively consume.)
 /// 
 /// Writing to, i.e., a file allows you to save assembled "binaries" that MLeM can execute; you can also pass 
 /// data over the network for distributed processing.
-pub fn program_to_writer(p: &Program, mut w: &mut Write) -> Result<(), serde_cbor::Error> {
+pub fn program_to_writer(p: &Program, mut w: &mut dyn Write) -> Result<(), serde_cbor::Error> {
     use serde_cbor::ser::to_writer_packed_sd;
     to_writer_packed_sd(&mut w, &p)
 }
@@ -0,0 +1,89 @@
+use std::io::{self, Write};
+use serde_cbor;
+use lex;
+use parse::{Assembly, AssembledLine, SymbolKind, DEFINES_NAME};
+
+/// Writes an assembler listing of an assembled program to a writer: each line of the program (once
+/// macros are expanded and files included), with where it is in the source, the index and CBOR
+/// encoding of each instruction it assembled to, and the values of the labels and constants it
+/// names. A table of every symbol follows.
+///
+/// Lines that came from a macro expansion have a `+` after their location. An instruction that
+/// can't be encoded is reported as an error of kind `InvalidData`.
+///
+/// ```
+/// use mlem_asm::*;
+/// let assembly = assemble_source("loop: sub R0 1\n  jnz loop R0\nhalt", "count.asm", &Options::default()).unwrap();
+/// let mut listing = Vec::new();
+/// listing_to_writer(&assembly, &mut listing).unwrap();
+/// assert_eq!(String::from_utf8(listing).unwrap(), "\
+/// count.asm:1  0  83 06 82 00 00 82 03 01  loop: sub R0 1  [loop = 0]
+/// count.asm:2  1  83 09 82 03 00 82 00 00    jnz loop R0  [loop = 0]
+/// count.asm:3  2  0c                       halt
+///
+/// Symbols:
+///   loop  label  0 (0x0)  count.asm:1
+/// ");
+/// ```
+pub fn listing_to_writer(assembly: &Assembly, w: &mut dyn Write) -> io::Result<()> {
+    let mut encodings = Vec::new();
+    for instruction in &assembly.program {
+        let bytes = serde_cbor::ser::to_vec_packed(instruction).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        encodings.push(bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "));
+    }
+    let locations: Vec<String> = assembly.lines.iter().map(location).collect();
+    let location_width = locations.iter().map(|l| l.len()).max().unwrap_or(0);
+    let index_width = assembly.program.len().saturating_sub(1).to_string().len();
+    let encoding_width = encodings.iter().map(|e| e.len()).max().unwrap_or(0);
+
+    for (line, location) in assembly.lines.iter().zip(&locations) {
+        let names = symbol_values(assembly, line);
+        let text = if names.is_empty() { line.text.clone() } else { format!("{}  [{}]", line.text, names.join(", ")) };
+        let mut instructions = line.instructions.clone();
+        // The first instruction goes beside the line; any more go on lines of their own below it.
+        let first = match instructions.next() {
+            Some(i) => format!("{:>iw$}  {:<ew$}", i, encodings[i], iw = index_width, ew = encoding_width),
+            None => " ".repeat(index_width + 2 + encoding_width),
+        };
+        writeln!(w, "{}", format!("{:<lw$}  {}  {}", location, first, text, lw = location_width).trim_end())?;
+        for i in instructions {
+            writeln!(w, "{:<lw$}  {:>iw$}  {}", "", i, encodings[i], lw = location_width, iw = index_width)?;
+        }
+    }
+
+    let mut symbols: Vec<_> = assembly.symbols.iter().collect();
+    symbols.sort_by(|a, b| a.0.cmp(b.0));
+    let kind_name = |kind: SymbolKind| match kind { SymbolKind::Label => "label", SymbolKind::Constant => "constant" };
+    let name_width = symbols.iter().map(|s| s.0.len()).max().unwrap_or(0);
+    let kind_width = symbols.iter().map(|s| kind_name(s.1.kind).len()).max().unwrap_or(0);
+    writeln!(w, "\nSymbols:")?;
+    for (name, symbol) in symbols {
+        let location = if symbol.location.file == DEFINES_NAME {
+            DEFINES_NAME.to_string()
+        } else {
+            format!("{}:{}", symbol.location.file, symbol.location.line + 1)
+        };
+        writeln!(w, "  {:<nw$}  {:<kw$}  {} ({:#x})  {}", name, kind_name(symbol.kind), symbol.value, symbol.value, location,
+                 nw = name_width, kw = kind_width)?;
+    }
+    Ok(())
+}
+
+/// Where a line of the program is in the source, as `file:line`, counted from one.
+fn location(line: &AssembledLine) -> String {
+    format!("{}:{}{}", line.file, line.line + 1, if line.expanded { "+" } else { "" })
+}
+
+/// The value of each label and constant a line names, as `name = value`, in the order they appear.
+fn symbol_values(assembly: &Assembly, line: &AssembledLine) -> Vec<String> {
+    let mut names: Vec<&str> = Vec::new();
+    // Names in string and character literals aren't symbols.
+    for token in lex::lex_line(&line.text).into_iter().filter(|t| !t.starts_with(['"', '\''])) {
+        for name in token.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_')) {
+            if !names.contains(&name) && assembly.symbols.get(name).is_some() {
+                names.push(name);
+            }
+        }
+    }
+    names.iter().map(|n| format!("{} = {}", n, assembly.symbols.get(n).unwrap().value)).collect()
+}
@@ -1,111 +1,470 @@
 extern crate mlem;
 extern crate mlem_asm;
-use mlem_asm::{parse_program, program_to_writer};
-use mlem::Machine;
-use std::io::prelude::*;
-use std::io::{BufReader, BufWriter, Stdout, Stdin};
-use std::fs::File;
-use std::borrow::Borrow;
+use mlem_asm::{assemble_file, program_to_writer, listing_to_writer, program_from_reader, is_assembled_program, disassemble, debug_info_to_writer, run_program, DebugInfo, Assembly, RunOptions, RunOutcome,
+               Options, Diagnostic, ErrorKind, Severity, Program, Level, LINTS};
+use mlem_asm::format::{format_source, Case, FormatOptions};
+use mlem_asm::parse::DEFINES_NAME;
+use std::io::{BufReader, BufWriter, IsTerminal, Read, Write};
+use std::fs::{self, File};
+use std::path::Path;
+use std::collections::HashMap;
+use std::time::Duration;
 
-fn usage() {
-    println!("Usage: ");
-    println!("\tmlem-asm a source [output]\tAssemble the given source file to the given output file, or stdout.");
-    println!("\tmlem-asm r source [input]\t\tAssemble the given source file and execute it, hooking up stdin and stdout.");
-}
+/// The program couldn't be assembled, or (with `fmt --check`) some files aren't formatted.
+const EXIT_FAILED: i32 = 1;
+/// The command line was wrong.
+const EXIT_USAGE: i32 = 2;
+/// A file couldn't be read or written.
+const EXIT_IO: i32 = 3;
+/// The program faulted while it was running, other than by an illegal instruction or going out of bounds.
+const EXIT_FAULT: i32 = 4;
+/// The program executed the most instructions allowed by `--max-cycles`.
+const EXIT_CYCLE_LIMIT: i32 = 5;
+/// The program ran for longer than `--timeout` allows.
+const EXIT_TIMEOUT: i32 = 6;
+/// The program executed an `illegal` instruction.
+const EXIT_ILLEGAL: i32 = 7;
+/// The program went outside the machine's memory, its stack, or the program itself.
+const EXIT_OUT_OF_BOUNDS: i32 = 8;
 
-enum Output {
-    File(BufWriter<File>),
-    StdOut(BufWriter<Stdout>)
-}
+/// The most words of memory `--memory` can give the machine: 128 MiB of them.
+const MAX_MEMORY: u64 = 1 << 24;
 
-enum Input {
-    File(BufReader<File>),
-    StdIn(BufReader<Stdin>)
+fn usage() {
+    println!("Usage: mlem-asm COMMAND [OPTIONS] FILE...");
+    println!();
+    println!("Commands:");
+    println!("\tassemble SOURCE...\tAssemble each source file to a file beside it, with the extension .bin.");
+    println!("\trun FILE\t\tExecute an assembled program, or assemble a source file and execute it, hooking up");
+    println!("\t\t\t\tstdin and stdout. Assembled programs are recognized by their CBOR tag. How the program");
+    println!("\t\t\t\tended is written to stderr.");
+    println!("\tcheck SOURCE...\t\tAssemble each source file and report any problems, without writing anything.");
+    println!("\tdisasm BINARY...\tDisassemble each assembled program to stdout.");
+    println!("\tfmt SOURCE...\t\tFormat each source file in place.");
+    println!();
+    println!("Options:");
+    println!("\t-o, --output PATH\tWrite to the given file (or stdout, for -) instead. Only for one file. (assemble, run, disasm)");
+    println!("\t-I PATH\t\t\tAlso look for included files in the given directory. (assemble, run, check)");
+    println!("\t-D NAME[=VALUE]\t\tDefine a constant (as 1, if no value is given) before assembling. (assemble, run, check)");
+    println!("\t--allow LINT, --warn LINT, --deny LINT");
+    println!("\t\t\t\tIgnore what the given lint finds, report it as a warning, or report it as an error. (assemble, run, check)");
+    println!("\t--listing PATH\t\tAlso write a listing of the program to the given file. Only for one file. (assemble)");
+    println!("\t--debug-info\t\tAlso write where each instruction came from in the source to a file beside the");
+    println!("\t\t\t\tassembled program, with the extension .dbg. (assemble)");
+    println!("\t--binary\t\tTreat the file as an assembled program, even without its CBOR tag. (run)");
+    println!("\t--input PATH\t\tGive the program the contents of the given file as input, instead of stdin. (run)");
+    println!("\t--input-string TEXT\tGive the program the given text as input, instead of stdin. (run)");
+    println!("\t--memory WORDS\t\tGive the machine the given number of words of memory (65535 by default, at most {}). (run)", MAX_MEMORY);
+    println!("\t--max-cycles COUNT\tStop the program once it has executed the given number of instructions. (run)");
+    println!("\t--timeout SECONDS\tStop the program once it has run for the given number of seconds. (run)");
+    println!("\t-q, --quiet\t\tDon't write how the program ended to stderr. (run)");
+    println!("\t--numbered\t\tFollow each line with the index of its instruction. (disasm)");
+    println!("\t--check\t\t\tChange nothing, but list the files that aren't formatted. (fmt)");
+    println!("\t--lowercase-registers\tWrite register names in lowercase. (fmt)");
+    println!("\t-h, --help\t\tShow this message.");
+    println!("\t-V, --version\t\tShow the version of mlem-asm.");
+    println!();
+    println!("Lints:");
+    for lint in LINTS.iter() {
+        println!("\t{:<20}{} ({} by default)", lint.name(), lint.description(), match lint.default_level() {
+            Level::Allow => "allowed",
+            Level::Warn => "a warning",
+            Level::Deny => "an error",
+        });
+    }
+    println!();
+    println!("Exit codes:");
+    println!("\t0\tSuccess.");
+    println!("\t{}\tA program couldn't be assembled, or (with fmt --check) some files aren't formatted.", EXIT_FAILED);
+    println!("\t{}\tThe command line was wrong.", EXIT_USAGE);
+    println!("\t{}\tA file couldn't be read or written.", EXIT_IO);
+    println!("\t{}\tThe program faulted while it was running, other than as below.", EXIT_FAULT);
+    println!("\t{}\tThe program executed the most instructions allowed by --max-cycles.", EXIT_CYCLE_LIMIT);
+    println!("\t{}\tThe program ran for longer than --timeout allows.", EXIT_TIMEOUT);
+    println!("\t{}\tThe program executed an illegal instruction.", EXIT_ILLEGAL);
+    println!("\t{}\tThe program went outside the machine's memory, its stack, or the program itself.", EXIT_OUT_OF_BOUNDS);
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum Mode {
+enum Command {
     Assemble,
-    Execute
+    Run,
+    Check,
+    Disassemble,
+    Format,
 }
 
-fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() > 4 || args.len() < 3 {
-        usage();
-        std::process::exit(101);
+/// The commands that assemble source, and so take the assembler's options.
+const ASSEMBLING: [Command; 3] = [Command::Assemble, Command::Run, Command::Check];
+
+impl Command {
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "assemble" => Some(Command::Assemble),
+            "run" => Some(Command::Run),
+            "check" => Some(Command::Check),
+            "disasm" => Some(Command::Disassemble),
+            "fmt" => Some(Command::Format),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            Command::Assemble => "assemble",
+            Command::Run => "run",
+            Command::Check => "check",
+            Command::Disassemble => "disasm",
+            Command::Format => "fmt",
+        }
     }
+}
+
+/// What the command line asks for: help, the version, or a command.
+enum Request {
+    Help,
+    Version,
+    Command(Box<Args>),
+}
 
-    let mode = match args[1].borrow() {
-        "a" => Mode::Assemble,
-        "r" => Mode::Execute,
-        other => { panic!("Unknown mode {}; try a for assemble or r for run", other); }
+/// Everything the command line asks a command to do.
+struct Args {
+    command: Command,
+    /// The files to work on, in order.
+    files: Vec<String>,
+    output: Option<String>,
+    options: Options,
+    listing: Option<String>,
+    debug_info: bool,
+    /// Whether to run the file as an assembled program, whether or not it looks like one.
+    binary: bool,
+    /// The file to give the program as input.
+    input: Option<String>,
+    /// The text to give the program as input.
+    input_string: Option<String>,
+    machine: RunOptions,
+    /// Whether to keep quiet about how the program ended.
+    quiet: bool,
+    numbered: bool,
+    check: bool,
+    format: FormatOptions,
+}
+
+/// Parse the arguments after the name of the program, or describe what's wrong with them.
+/// `--help` and `--version` are only recognized where an option could be, not as the value of one.
+fn parse_args(args: &[String]) -> Result<Request, String> {
+    let (name, rest) = args.split_first().ok_or("No command given")?;
+    match name.as_str() {
+        "-h" | "--help" => return Ok(Request::Help),
+        "-V" | "--version" => return Ok(Request::Version),
+        _ => {}
+    }
+    let command = Command::from_name(name).ok_or_else(|| format!("Unknown command: {}", name))?;
+    let mut parsed = Args {
+        command, files: Vec::new(), output: None, options: Options::default(), listing: None,
+        debug_info: false, binary: false, input: None, input_string: None,
+        machine: RunOptions::default(), quiet: false, numbered: false, check: false, format: FormatOptions::default(),
     };
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        let applies = |commands: &[Command]| match commands.contains(&command) {
+            true => Ok(()),
+            false => Err(format!("{} can't be used with {}", arg, command.name())),
+        };
+        let mut value = |what: &str| rest.next().cloned().ok_or_else(|| format!("{} needs {}", arg, what));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Request::Help),
+            "-V" | "--version" => return Ok(Request::Version),
+            "-o" | "--output" => {
+                applies(&[Command::Assemble, Command::Run, Command::Disassemble])?;
+                parsed.output = Some(value("a path")?);
+            }
+            "-I" => {
+                applies(&ASSEMBLING)?;
+                parsed.options.include_paths.push(value("a path")?.into());
+            }
+            "-D" => {
+                applies(&ASSEMBLING)?;
+                define(&mut parsed.options, &value("a definition")?)?;
+            }
+            "--listing" => {
+                applies(&[Command::Assemble])?;
+                parsed.listing = Some(value("a path")?);
+            }
+            "--debug-info" => { applies(&[Command::Assemble])?; parsed.debug_info = true; }
+            "--binary" => { applies(&[Command::Run])?; parsed.binary = true; }
+            "--input" => {
+                applies(&[Command::Run])?;
+                parsed.input = Some(value("a path")?);
+            }
+            "--input-string" => {
+                applies(&[Command::Run])?;
+                parsed.input_string = Some(value("some text")?);
+            }
+            "--memory" => {
+                applies(&[Command::Run])?;
+                parsed.machine.memory = number(arg, &value("a number of words")?).and_then(|m| match m {
+                    0 => Err("--memory must be at least 1".into()),
+                    m if m > MAX_MEMORY => Err(format!("--memory can be at most {}", MAX_MEMORY)),
+                    m => Ok(m as usize),
+                })?;
+            }
+            "--max-cycles" => {
+                applies(&[Command::Run])?;
+                parsed.machine.max_cycles = Some(number(arg, &value("a number of instructions")?)?);
+            }
+            "--timeout" => {
+                applies(&[Command::Run])?;
+                let seconds = value("a number of seconds")?;
+                let timeout = seconds.parse::<f64>().ok().and_then(|s| Duration::try_from_secs_f64(s).ok());
+                parsed.machine.timeout = Some(timeout.ok_or_else(|| format!("Invalid number of seconds for --timeout: {}", seconds))?);
+            }
+            "-q" | "--quiet" => { applies(&[Command::Run])?; parsed.quiet = true; }
+            "--numbered" => { applies(&[Command::Disassemble])?; parsed.numbered = true; }
+            "--check" => { applies(&[Command::Format])?; parsed.check = true; }
+            "--lowercase-registers" => { applies(&[Command::Format])?; parsed.format.register_case = Case::Lower; }
+            _ if lint_level(arg).is_some() => {
+                applies(&ASSEMBLING)?;
+                let name = value("the name of a lint")?;
+                parsed.options.set_lint(&name, lint_level(arg).unwrap()).map_err(|e| match e.suggestion {
+                    Some(s) => format!("{}; did you mean {}?", e.message, s),
+                    None => e.message,
+                })?;
+            }
+            _ if arg.starts_with("-I") => {
+                applies(&ASSEMBLING)?;
+                parsed.options.include_paths.push(arg[2..].into());
+            }
+            _ if arg.starts_with("-D") => {
+                applies(&ASSEMBLING)?;
+                define(&mut parsed.options, &arg[2..])?;
+            }
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("Unknown option: {}", arg)),
+            _ => parsed.files.push(arg.clone()),
+        }
+    }
 
-    load_and_process(args, mode);
-    
+    if parsed.files.is_empty() {
+        return Err(format!("{} needs a file to work on", command.name()));
+    }
+    if command == Command::Run && parsed.files.len() > 1 {
+        return Err("run takes one program".into());
+    }
+    if parsed.input.is_some() && parsed.input_string.is_some() {
+        return Err("--input and --input-string can't be used together".into());
+    }
+    if parsed.files.len() > 1 && parsed.output.is_some() {
+        return Err("--output can only be used with one file".into());
+    }
+    if parsed.files.len() > 1 && parsed.listing.is_some() {
+        return Err("--listing can only be used with one file".into());
+    }
+    if parsed.debug_info && parsed.output.as_ref().is_some_and(|o| o == "-") {
+        return Err("--debug-info can't be used when writing to stdout".into());
+    }
+    Ok(Request::Command(Box::new(parsed)))
+}
+
+/// Define a constant from a `-D` option.
+fn define(options: &mut Options, definition: &str) -> Result<(), String> {
+    options.define(definition).map_err(|e| format!("Invalid definition: {}", e.message))
 }
 
-fn load_and_process(args: Vec<String>, mode: Mode) {
-    let mut f = BufReader::new(File::open(&args[2]).unwrap());
-    let mut contents = String::new();
-    f.read_to_string(&mut contents).unwrap();
+/// Parse the number given to an option.
+fn number(option: &str, value: &str) -> Result<u64, String> {
+    value.parse().map_err(|_| format!("Invalid number for {}: {}", option, value))
+}
 
-    let mut out: Option<Output> = None;
-    let mut inp: Option<Input> = None;
+/// The lint level set by an option, if it is one of `--allow`, `--warn` or `--deny`.
+fn lint_level(arg: &str) -> Option<Level> {
+    match arg {
+        "--allow" => Some(Level::Allow),
+        "--warn" => Some(Level::Warn),
+        "--deny" => Some(Level::Deny),
+        _ => None,
+    }
+}
 
-    match mode {
-        Mode::Assemble => {
-            out = Some(if args.len() == 4 {
-                Output::File(BufWriter::new(File::create(&args[3]).unwrap()))
-            } else {
-                Output::StdOut(BufWriter::new(std::io::stdout()))
-            });
-        },
-        Mode::Execute => {
-            inp = Some(if args.len() == 4 {
-                Input::File(BufReader::new(File::create(&args[3]).unwrap()))
-            } else {
-                Input::StdIn(BufReader::new(std::io::stdin()))
-            });
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(Request::Command(a)) => *a,
+        Ok(Request::Help) => {
+            usage();
+            std::process::exit(0);
+        }
+        Ok(Request::Version) => {
+            println!("mlem-asm {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{}\nTry `mlem-asm --help` for more information.", e);
+            std::process::exit(EXIT_USAGE);
         }
     };
+    let status = match args.command {
+        Command::Assemble => each_file(&args, assemble_to_file),
+        Command::Run => run(&args),
+        Command::Check => each_file(&args, |file, args| assemble_reporting(file, &args.options).map(|_| ())),
+        Command::Disassemble => each_file(&args, disassemble_file),
+        Command::Format => format_files(&args),
+    };
+    std::process::exit(status);
+}
 
-    let result = parse_program(&contents);
-
-    match result {
-        Ok(p) => {
-            match mode {
-                Mode::Assemble => {
-                    match out.unwrap() {
-                        Output::File(mut o) => { program_to_writer(&p, &mut o).unwrap(); },
-                        Output::StdOut(mut o) => { program_to_writer(&p, &mut o).unwrap(); }
-                    }
-                },
-                Mode::Execute => {
-                    match inp.unwrap() {
-                        Input::File(mut i) => { 
-                            let mut o = std::io::stdout();
-                            let mut m = Machine::new(65535, &mut i, &mut o);
-                            m.load_program(p);
-                            println!("\n{:?}", m.run()) 
-                        },
-                        Input::StdIn(mut i) => { 
-                            let mut o = std::io::stdout();
-                            let mut m = Machine::new(65535, &mut i, &mut o);
-                            m.load_program(p);
-                            println!("\n{:?}", m.run()) 
-                        }
-                    }
-                }
-            }
-         }
+/// Do something to each of the files in the arguments, even if it fails for some of them, and
+/// give the exit code of the first that failed, or 0 if none did.
+fn each_file<F: Fn(&str, &Args) -> Result<(), i32>>(args: &Args, f: F) -> i32 {
+    args.files.iter().fold(0, |status, file| match f(file, args) {
+        Err(code) if status == 0 => code,
+        _ => status,
+    })
+}
+
+/// Assemble a source file, reporting any errors and warnings; if it can't be assembled, give the exit code.
+fn assemble_reporting(file: &str, options: &Options) -> Result<Assembly, i32> {
+    match assemble_file(file, options) {
+        Ok(assembly) => {
+            report(&assembly.warnings);
+            Ok(assembly)
+        }
         Err(e) => {
-            println!("Could not assemble program.");
-            for error in e {
-                println!("{:2}: {}", error.0, error.1);
-            }
-            std::process::exit(1);
+            report(&e);
+            Err(if e.iter().any(|d| d.kind == ErrorKind::Io) { EXIT_IO } else { EXIT_FAILED })
+        }
+    }
+}
+
+/// Write bytes to a file, or to stdout if the path is `-`.
+fn write_output(path: &str, bytes: &[u8]) -> Result<(), i32> {
+    let written = if path == "-" { std::io::stdout().write_all(bytes) } else { fs::write(path, bytes) };
+    written.map_err(|e| {
+        eprintln!("Could not write {}: {}", path, e);
+        EXIT_IO
+    })
+}
+
+/// Assemble a source file to the output file, or to a file beside it with the extension `.bin`,
+/// along with its listing and debug information, if they were asked for.
+fn assemble_to_file(file: &str, args: &Args) -> Result<(), i32> {
+    let assembly = assemble_reporting(file, &args.options)?;
+    let output = match args.output {
+        Some(ref o) => o.clone(),
+        None => Path::new(file).with_extension("bin").display().to_string(),
+    };
+    if let Some(ref path) = args.listing {
+        let mut text = Vec::new();
+        listing_to_writer(&assembly, &mut text).map_err(|e| { eprintln!("Could not write listing: {}", e); EXIT_IO })?;
+        write_output(path, &text)?;
+    }
+    if args.debug_info {
+        let mut bytes = Vec::new();
+        debug_info_to_writer(&DebugInfo::new(&assembly), &mut bytes)
+            .map_err(|e| { eprintln!("Could not write debug information: {}", e); EXIT_IO })?;
+        write_output(&Path::new(&output).with_extension("dbg").display().to_string(), &bytes)?;
+    }
+    let mut bytes = Vec::new();
+    program_to_writer(&assembly.program, &mut bytes).map_err(|e| { eprintln!("Could not write {}: {}", output, e); EXIT_IO })?;
+    write_output(&output, &bytes)
+}
+
+/// Execute the program in the arguments, assembling it first if it is source.
+fn run(args: &Args) -> i32 {
+    let program = match load_program(&args.files[0], args) {
+        Ok(p) => p,
+        Err(code) => return code,
+    };
+    let mut input: Box<dyn Read> = match (&args.input, &args.input_string) {
+        (Some(path), _) => match File::open(path) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(e) => { eprintln!("Could not read {}: {}", path, e); return EXIT_IO; }
+        },
+        (None, Some(text)) => Box::new(text.as_bytes()),
+        (None, None) => Box::new(BufReader::new(std::io::stdin())),
+    };
+    let mut output: Box<dyn Write> = match args.output {
+        Some(ref path) if path != "-" => match File::create(path) {
+            Ok(f) => Box::new(BufWriter::new(f)),
+            Err(e) => { eprintln!("Could not write {}: {}", path, e); return EXIT_IO; }
+        },
+        _ => Box::new(std::io::stdout()),
+    };
+    let (outcome, _) = run_program(program, &mut input, &mut output, &args.machine);
+    if let Err(e) = output.flush() {
+        eprintln!("Could not write the program's output: {}", e);
+        return EXIT_IO;
+    }
+    if !args.quiet {
+        // The program's output might not end with a newline; the outcome goes on a line of its own.
+        eprintln!("\n{:?}", outcome);
+    }
+    match outcome {
+        RunOutcome::Halt => 0,
+        RunOutcome::Illegal => EXIT_ILLEGAL,
+        RunOutcome::OutOfBounds(_) => EXIT_OUT_OF_BOUNDS,
+        RunOutcome::Fault(_) => EXIT_FAULT,
+        RunOutcome::CycleLimit => EXIT_CYCLE_LIMIT,
+        RunOutcome::Timeout => EXIT_TIMEOUT,
+    }
+}
+
+/// Read a program from a file: as it is, if it is an assembled program (or `--binary` was given),
+/// and otherwise by assembling it.
+fn load_program(file: &str, args: &Args) -> Result<Program, i32> {
+    let bytes = fs::read(file).map_err(|e| { eprintln!("Could not read {}: {}", file, e); EXIT_IO })?;
+    if args.binary || is_assembled_program(&bytes) {
+        program_from_reader(&mut &bytes[..]).map_err(|e| { eprintln!("{} is not an assembled program: {}", file, e); EXIT_FAILED })
+    } else {
+        assemble_reporting(file, &args.options).map(|a| a.program)
+    }
+}
+
+/// Disassemble an assembled program to the output file, or stdout.
+fn disassemble_file(file: &str, args: &Args) -> Result<(), i32> {
+    let program = match File::open(file) {
+        Ok(f) => program_from_reader(&mut BufReader::new(f)),
+        Err(e) => { eprintln!("Could not read {}: {}", file, e); return Err(EXIT_IO); }
+    };
+    let program = program.map_err(|e| { eprintln!("{} is not an assembled program: {}", file, e); EXIT_FAILED })?;
+    let text = disassemble(&program, &FormatOptions::default(), args.numbered)
+        .map_err(|e| { eprintln!("Could not disassemble {}: {}", file, e); EXIT_FAILED })?;
+    write_output(args.output.as_ref().map_or("-", |o| o.as_str()), text.as_bytes())
+}
+
+/// Format each of the source files in the arguments in place. With `--check`, the files aren't
+/// changed; instead, the ones that aren't formatted are listed, and the command fails if there are any.
+fn format_files(args: &Args) -> i32 {
+    each_file(args, |file, args| {
+        let source = fs::read_to_string(file).map_err(|e| { eprintln!("Could not read {}: {}", file, e); EXIT_IO })?;
+        let formatted = format_source(&source, &args.format);
+        if formatted == source {
+            Ok(())
+        } else if args.check {
+            println!("{} is not formatted.", file);
+            Err(EXIT_FAILED)
+        } else {
+            write_output(file, formatted.as_bytes())
         }
+    })
+}
+
+/// Print the errors and warnings found assembling a program to stderr, each with the line of source
+/// it's on, then a count of the errors, if there are any.
+/// Color is used if stderr is a terminal, unless the NO_COLOR environment variable is set.
+fn report(diagnostics: &[Diagnostic]) {
+    let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    // Diagnostics can be in any of the files a program includes; each is only read once. Those in
+    // definitions from the command line aren't in a file at all.
+    let mut sources: HashMap<&str, Option<String>> = HashMap::new();
+    for diagnostic in diagnostics {
+        let source = sources.entry(&diagnostic.file)
+            .or_insert_with(|| if diagnostic.file == DEFINES_NAME { None } else { fs::read_to_string(&diagnostic.file).ok() });
+        let line = source.as_ref().and_then(|s| s.lines().nth(diagnostic.line as usize));
+        eprintln!("{}\n", diagnostic.render(line, color));
     }
-}
\ No newline at end of file
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    if errors > 0 {
+        let count = if errors == 1 { "1 error".to_string() } else { format!("{} errors", errors) };
+        eprintln!("Could not assemble program due to {}.", count);
+    }
+}
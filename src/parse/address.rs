@@ -1,88 +1,215 @@
 #![allow(dead_code)]
 use mlem::{Register, Address};
-use std::borrow::Borrow;
+use super::diagnostic::{Diagnostic, ErrorKind};
+use super::label::is_identifier;
+use super::suggest::suggest;
+use super::symbol::{Location, Symbols};
+use super::expr::evaluate;
+
+/// Every register name, with the register it names.
+pub const REGISTER_NAMES: [(&str, Register); 10] = [
+    ("r0", Register::R0), ("r1", Register::R1), ("r2", Register::R2), ("r3", Register::R3),
+    ("r4", Register::R4), ("r5", Register::R5), ("r6", Register::R6), ("r7", Register::R7),
+    ("rsp", Register::SP), ("rbp", Register::BP),
+];
 
 /// Parse a register name into a Register or a reason why it could not.
-/// All register names begin with an R (case insensitive).
-pub fn parse_register_name(item: &str) -> Result<Register, String> { 
-    match item.to_lowercase().borrow() {
-        "r0" => Ok(Register::R0),
-        "r1" => Ok(Register::R1),
-        "r2" => Ok(Register::R2),
-        "r3" => Ok(Register::R3),
-        "r4" => Ok(Register::R4),
-        "r5" => Ok(Register::R5),
-        "r6" => Ok(Register::R6),
-        "r7" => Ok(Register::R7),
-        "rsp" => Ok(Register::SP),
-        "rbp" => Ok(Register::BP),
-        other => Err(format!("Unknown register name: {}", other))
+/// All register names begin with an R (case insensitive). If the name isn't a register, the
+/// error suggests the closest one that is.
+///
+/// ```
+/// use mlem_asm::Register;
+/// use mlem_asm::parse::{parse_register_name, ErrorKind};
+/// assert_eq!(parse_register_name("RSP"), Ok(Register::SP));
+/// let error = parse_register_name("rps").unwrap_err();
+/// assert_eq!((error.kind, error.columns), (ErrorKind::UnknownRegister, 0..3));
+/// assert_eq!(error.suggestion, Some("rsp".into()));
+/// ```
+pub fn parse_register_name(item: &str) -> Result<Register, Diagnostic> {
+    let lowercase = item.to_lowercase();
+    if let Some(&(_, register)) = REGISTER_NAMES.iter().find(|r| r.0 == lowercase) {
+        return Ok(register);
     }
- }
+    let error = Diagnostic::new(ErrorKind::UnknownRegister, format!("Unknown register name: {}", lowercase)).covering(item);
+    let names: Vec<&str> = REGISTER_NAMES.iter().map(|r| r.0).collect();
+    match suggest(&lowercase, &names) {
+        Some(s) => Err(error.with_suggestion(s)),
+        None => Err(error),
+    }
+}
 
 // Parse an address of the form "specifier:item" where specifier is one of "R", "L", "M", or "P"
 // and item is an appropriate specifier.
-// Literals can be decimal, hexidecimal (with 0x___), or binary (with 0b___).
-pub fn parse_address(name: &str) -> Result<Address, String> {
+// Literals can be decimal, hexidecimal (with 0x___), binary (with 0b___), or characters (like 'A').
+// Anywhere a literal is accepted, a constant expression over literals, labels and constants may
+// be used instead (see `expr::evaluate`); `at` is where the address appears, which decides
+// whether a constant has been defined yet.
+// The columns of any error are relative to the start of the address.
+pub fn parse_address(name: &str, symbols: &Symbols, at: &Location) -> Result<Address, Diagnostic> {
     let first_character: char = match name.to_lowercase().chars().next() {
         Some(v) => v,
-        None => return Err("Cannot parse empty address.".into())
+        None => return Err(Diagnostic::new(ErrorKind::InvalidAddress, "Cannot parse empty address."))
     };
 
-    if first_character == 'r' {
-        match parse_register_name(&name) {
+    if first_character == '*' {
+        let item = &name[1..];
+        if let Ok(r) = parse_register_name(item) {
+            return Ok(Address::MemReg(r));
+        }
+        match parse_value(item, symbols, at) {
+            Ok(l) => Ok(Address::MemAbs(l)),
+            // A name shaped like a register might have been meant as either.
+            Err(literal_parse_error) if looks_like_register(item, symbols) => {
+                let register_error = parse_register_name(item).unwrap_err();
+                let error = Diagnostic::new(
+                    ErrorKind::InvalidAddress,
+                    format!("Expected a register or memory address, failed to parse either. {}, {}",
+                            literal_parse_error.message, register_error.message)
+                ).covering(name);
+                // Most likely a register was meant, so suggest one.
+                Err(match register_error.suggestion {
+                    Some(s) => error.with_suggestion(format!("*{}", s)),
+                    None => error,
+                })
+            }
+            Err(e) => Err(suggest_register(e, item, "*").shifted(1))
+        }
+    } else if looks_like_register(name, symbols) {
+        match parse_register_name(name) {
             Ok(r) => Ok(Address::RegAbs(r)),
             Err(e) => Err(e)
         }
-    } else if first_character.is_digit(10) {
-        // Either this starts with a radix specifier (a 0 and a letter) or a number.
-        match parse_literal(&name) {
+    } else if first_character.is_ascii_alphanumeric() || "_(~'".contains(first_character) {
+        // A number (possibly with a radix specifier), a character, a name, or some expression over them.
+        match parse_value(name, symbols, at) {
             Ok(l) => Ok(Address::Literal(l)),
-            Err(e) => Err(e)
-        }
-    } else if first_character == '*' {
-        match parse_literal(&name[1..]) {
-            Ok(l) => Ok(Address::MemAbs(l)),
-            Err(literal_parse_error) => { 
-                match parse_register_name(&name[1..]) {
-                    Ok(r) => Ok(Address::MemReg(r)),
-                    Err(register_parse_error) => Err(
-                        format!("Expected a register or memory address, failed to parse either. {}, {}", literal_parse_error, register_parse_error) 
-                    )
-                }
-            }
+            Err(e) => Err(suggest_register(e, name, ""))
         }
     } else {
-        Err(format!("Unknown address type specifier: {} (expected r, *, or digit).", first_character))
+        Err(Diagnostic::new(ErrorKind::InvalidAddress,
+                            format!("Unknown address type specifier: {} (expected r, *, digit, name, or expression).", first_character))
+            .with_columns(0..first_character.len_utf8()))
+    }
+}
+
+/// Decide whether an item should be parsed as a register: it is shaped like a register name (`R`
+/// and some digits, `RSP` or `RBP`), and is not the name of a symbol. Other names are symbols.
+fn looks_like_register(item: &str, symbols: &Symbols) -> bool {
+    let lowercase = item.to_lowercase();
+    let shaped = match lowercase.strip_prefix('r') {
+        Some("sp") | Some("bp") => true,
+        Some(digits) => !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    };
+    shaped && symbols.get(item).is_none()
+}
+
+/// A name that isn't a symbol, but is close to the name of a register, was probably meant to be a
+/// register; if nothing closer was suggested, suggest that one (after `prefix`).
+fn suggest_register(error: Diagnostic, name: &str, prefix: &str) -> Diagnostic {
+    if error.kind != ErrorKind::UndefinedSymbol || error.suggestion.is_some() || !is_identifier(name) {
+        return error;
     }
+    let names: Vec<&str> = REGISTER_NAMES.iter().map(|r| r.0).collect();
+    match suggest(&name.to_lowercase(), &names) {
+        Some(s) => error.with_suggestion(format!("{}{}", prefix, s)),
+        None => error,
+    }
+}
+
+/// Evaluate a literal, symbol, or constant expression to a value.
+pub fn parse_value(item: &str, symbols: &Symbols, at: &Location) -> Result<u64, Diagnostic> {
+    evaluate(item, symbols, at)
 }
 
-fn parse_literal(item: &str) -> Result<u64, String> {
+pub fn parse_literal(item: &str) -> Result<u64, Diagnostic> {
     // Check if there is a radix specifier
-    let non_decimal_radix: Option<u32>;
-    if item.len() < 2 {
-        non_decimal_radix = None;
+    let non_decimal_radix: Option<u32> = if item.len() < 2 {
+        None
     } else {
-        non_decimal_radix = match &item[0..2] {
+        match &item[0..2] {
             "0x" => Some(16),
             "0b" => Some(2),
             _ => None,
-        };
-    }
+        }
+    };
 
     //Parse the literal value
     match non_decimal_radix {
         Some(radix) => {
             match u64::from_str_radix(&item[2..item.len()], radix) {
                 Ok(v) => Ok(v),
-                Err(e) => Err(format!("Could not parse literal of base : {}", e))
+                Err(e) => Err(Diagnostic::new(ErrorKind::InvalidLiteral, format!("Could not parse literal of base : {}", e)).covering(item))
             }
         }
         None => {
-            match u64::from_str_radix(item, 10) {
+            match item.parse::<u64>() {
                 Ok(v) => Ok(v),
-                Err(e) => Err(format!("Could not parse literal: {}", e))
+                Err(e) => Err(Diagnostic::new(ErrorKind::InvalidLiteral, format!("Could not parse literal: {}", e)).covering(item))
             }
         }
     }
  }
+
+/// Parse a character literal like `'A'`, `'\n'`, or `'\x7f'` into its character code.
+pub fn parse_char_literal(item: &str) -> Result<u64, Diagnostic> {
+    if item.len() < 2 || !item.starts_with('\'') || !item.ends_with('\'') {
+        return Err(Diagnostic::new(ErrorKind::InvalidLiteral, format!("Unterminated character literal: {}", item)).covering(item));
+    }
+    let characters = unescape(&item[1..item.len() - 1]).map_err(|e| e.shifted(1))?;
+    if characters.len() != 1 {
+        return Err(Diagnostic::new(ErrorKind::InvalidLiteral,
+                                   format!("Character literal must contain exactly one character: {}", item)).covering(item));
+    }
+    Ok(characters[0] as u64)
+}
+
+/// Parse a string literal like `"Hello, world\n"` into its characters.
+pub fn parse_string_literal(item: &str) -> Result<Vec<char>, Diagnostic> {
+    if item.len() < 2 || !item.starts_with('"') || !item.ends_with('"') {
+        return Err(Diagnostic::new(ErrorKind::InvalidLiteral, format!("Expected a string literal in double quotes: {}", item)).covering(item));
+    }
+    unescape(&item[1..item.len() - 1]).map_err(|e| e.shifted(1))
+}
+
+/// Replace the escape sequences in the body of a character or string literal with the characters
+/// they stand for. Supported escapes are `\n`, `\r`, `\t`, `\0`, `\\`, `\'`, `\"`, and `\xHH`.
+/// The columns of any error are those of the bad escape sequence in the body.
+pub fn unescape(body: &str) -> Result<Vec<char>, Diagnostic> {
+    let mut v = Vec::new();
+    let mut characters = body.char_indices();
+    while let Some((start, c)) = characters.next() {
+        if c != '\\' {
+            v.push(c);
+            continue;
+        }
+        let sequence = match characters.next().map(|(_, c)| c) {
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('0') => Ok('\0'),
+            Some('\\') => Ok('\\'),
+            Some('\'') => Ok('\''),
+            Some('"') => Ok('"'),
+            Some('x') => {
+                let digits: String = characters.by_ref().take(2).map(|(_, c)| c).collect();
+                // Checked before parsing, which would allow a sign, as in `\x+1`.
+                if digits.len() == 2 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                    Ok(u8::from_str_radix(&digits, 16).unwrap() as char)
+                } else {
+                    Err(format!("Invalid escape sequence: \\x{} (expected two hex digits)", digits))
+                }
+            }
+            Some(other) => Err(format!("Unknown escape sequence: \\{}", other)),
+            None => Err("Escape sequence cut off by the end of the literal.".into()),
+        };
+        match sequence {
+            Ok(c) => v.push(c),
+            Err(message) => {
+                let end = body.len() - characters.as_str().len();
+                return Err(Diagnostic::new(ErrorKind::InvalidLiteral, message).with_columns(start..end));
+            }
+        }
+    }
+    Ok(v)
+}
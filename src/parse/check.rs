@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use mlem::{Address, Instruction, Program};
+use super::lex;
+use super::diagnostic::Diagnostic;
+use super::label::label_definition;
+use super::lint::Lint;
+use super::preprocess::SourceLine;
+use super::symbol::{SymbolKind, Symbols};
+
+/// Where an assembled instruction came from, for problems that can only be found once the
+/// whole program is assembled.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Origin {
+    /// The position in the preprocessed program of the line the instruction is on.
+    pub position: usize,
+    /// The columns of the whole statement in that line.
+    pub statement: Range<usize>,
+    /// The columns of each of the statement's operands in that line.
+    pub operands: Vec<Range<usize>>,
+}
+
+impl Origin {
+    /// The origin of the instructions `pieces` of `line` assemble to, at `position`.
+    pub fn new(position: usize, line: &str, pieces: &[&str]) -> Self {
+        let columns = |piece: &str| { let start = lex::offset(line, piece); start..start + piece.len() };
+        let start = pieces.first().map_or(0, |p| columns(p.trim()).start);
+        let end = pieces.last().map_or(0, |p| columns(p.trim()).end);
+        Origin { position, statement: start..end, operands: pieces.iter().skip(1).map(|p| columns(p.trim())).collect() }
+    }
+}
+
+/// Something a lint found: the position in the program whose lint levels decide how it is
+/// reported, the lint, and the diagnostic describing it.
+pub type Finding = (usize, Lint, Diagnostic);
+
+/// Run every lint over an assembled program.
+///
+/// `origins` are where each instruction of `program` came from, in `lines`, and `symbols` are
+/// the symbols it defined, each marked as used or not.
+pub fn lint_program(program: &Program, origins: &[Origin], lines: &[SourceLine], symbols: &Symbols) -> Vec<Finding> {
+    let mut findings = check_jumps(program, origins, lines);
+    findings.extend(check_reachability(program, origins, lines, symbols));
+    findings.extend(check_instructions(program, origins, lines));
+    findings.extend(check_halt(program, origins, lines));
+    findings.extend(check_labels(lines, symbols));
+    findings
+}
+
+/// Report a finding about the instruction from `origin`, covering `columns` of its line.
+fn finding(lint: Lint, message: String, origin: &Origin, columns: Range<usize>, lines: &[SourceLine]) -> Finding {
+    let diagnostic = Diagnostic::new(lint.kind(), message).with_columns(columns);
+    (origin.position, lint, lines[origin.position].locate(diagnostic))
+}
+
+/// The target of a jump instruction, if it is one.
+pub(crate) fn jump_target(instruction: &Instruction) -> Option<Address> {
+    match *instruction {
+        Instruction::Jump(a) | Instruction::JumpIfZero(a, _) | Instruction::JumpNotZero(a, _) => Some(a),
+        _ => None,
+    }
+}
+
+/// Check that every jump in a program lands inside it. Jumps to literal targets past the end of the
+/// program are certain to fault; jumps to targets computed at run time (from a register or memory)
+/// can't be checked.
+fn check_jumps(program: &Program, origins: &[Origin], lines: &[SourceLine]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (instruction, origin) in program.iter().zip(origins) {
+        let target = match jump_target(instruction) {
+            Some(target) => target,
+            None => continue,
+        };
+        let columns = origin.operands.first().cloned().unwrap_or(0..0);
+        let text = lines[origin.position].text.get(columns.clone()).unwrap_or("");
+        let (lint, message) = match target {
+            Address::Literal(t) if t < program.len() as u64 => continue,
+            Address::Literal(t) => (Lint::JumpOutOfRange,
+                format!("Jump target {} is outside the program, which has {} instructions.", t, program.len())),
+            _ => (Lint::ComputedJump,
+                format!("Jump target {} is computed at run time, so it can't be checked against the length of the program.", text)),
+        };
+        findings.push(finding(lint, message, origin, columns, lines));
+    }
+    findings
+}
+
+/// Find instructions after a `halt` or `jump` that nothing leads to: no label marks them, and no
+/// jump targets them. Only the first of each run of such instructions is reported. If any jump
+/// is computed at run time, it might lead anywhere, so nothing is reported.
+fn check_reachability(program: &Program, origins: &[Origin], lines: &[SourceLine], symbols: &Symbols) -> Vec<Finding> {
+    let mut targets = HashSet::new();
+    for instruction in program {
+        match jump_target(instruction) {
+            Some(Address::Literal(t)) => { targets.insert(t); },
+            Some(_) => return Vec::new(),
+            None => {},
+        }
+    }
+    targets.extend(symbols.iter().filter(|&(_, s)| s.kind == SymbolKind::Label).map(|(_, s)| s.value));
+
+    let mut findings = Vec::new();
+    let mut reachable = true;
+    // What made the following instructions unreachable, until the first of them is reported.
+    let mut cause = None;
+    for (index, (instruction, origin)) in program.iter().zip(origins).enumerate() {
+        if targets.contains(&(index as u64)) {
+            reachable = true;
+        }
+        if !reachable {
+            if let Some(cause) = cause.take() {
+                let message = format!("Unreachable instruction: it follows a {}, and no label or jump leads to it.", cause);
+                findings.push(finding(Lint::Unreachable, message, origin, origin.statement.clone(), lines));
+            }
+        } else {
+            match *instruction {
+                Instruction::Halt => { reachable = false; cause = Some("halt"); },
+                Instruction::Jump(_) => { reachable = false; cause = Some("jump"); },
+                _ => {},
+            }
+        }
+    }
+    findings
+}
+
+/// Find instructions that do nothing: moves of a location to itself, and subtractions of zero.
+fn check_instructions(program: &Program, origins: &[Origin], lines: &[SourceLine]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (instruction, origin) in program.iter().zip(origins) {
+        match *instruction {
+            Instruction::Move(a, b) if a == b => {
+                let text = origin.operands.first().and_then(|c| lines[origin.position].text.get(c.clone())).unwrap_or("");
+                let message = format!("Moving {} to itself does nothing.", text);
+                findings.push(finding(Lint::SelfMove, message, origin, origin.statement.clone(), lines));
+            }
+            Instruction::Sub(_, Address::Literal(0)) => {
+                let columns = origin.operands.get(1).cloned().unwrap_or_else(|| origin.statement.clone());
+                findings.push(finding(Lint::SubZero, "Subtracting zero does nothing.".into(), origin, columns, lines));
+            }
+            _ => {},
+        }
+    }
+    findings
+}
+
+/// Check that a program has a `halt` instruction; without one, it can only stop by faulting.
+/// This is reported on the last instruction, at the end of the program.
+fn check_halt(program: &Program, origins: &[Origin], lines: &[SourceLine]) -> Vec<Finding> {
+    match origins.last() {
+        Some(last) if !program.contains(&Instruction::Halt) => {
+            let message = "The program has no halt instruction, so it can only stop by faulting.".to_string();
+            let (_, lint, diagnostic) = finding(Lint::NoHalt, message, last, last.statement.clone(), lines);
+            vec![(lines.len(), lint, diagnostic)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Find labels that are never used, in the order they are defined.
+fn check_labels(lines: &[SourceLine], symbols: &Symbols) -> Vec<Finding> {
+    let mut labels: Vec<_> = symbols.iter()
+        .filter(|&(name, s)| s.kind == SymbolKind::Label && !symbols.is_used(name))
+        .collect();
+    labels.sort_by_key(|&(_, s)| s.location.position);
+    labels.into_iter().filter_map(|(name, symbol)| {
+        let position = symbol.location.position as usize;
+        let line = lines.get(position)?;
+        let token = lex::lex_line(&line.text).into_iter().find(|t| label_definition(t) == Some(name.as_str()))?;
+        let columns = lex::offset(&line.text, token)..lex::offset(&line.text, token) + name.len();
+        let diagnostic = Diagnostic::new(Lint::UnusedLabel.kind(), format!("Label {} is never used.", name)).with_columns(columns);
+        Some((position, Lint::UnusedLabel, line.locate(diagnostic)))
+    }).collect()
+}
@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use lex;
+use super::lint::Lint;
+
+/// The kinds of problem the assembler can find in a program.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum ErrorKind {
+    /// A name in instruction position that is not an instruction, pseudo-instruction, or macro.
+    UnknownInstruction,
+    /// A name in register position that is not a register.
+    UnknownRegister,
+    /// An operand that can't be any kind of address.
+    InvalidAddress,
+    /// An operand of a kind its instruction can't use, like a literal to be written to.
+    InvalidOperand,
+    /// A malformed number, character, or string literal.
+    InvalidLiteral,
+    /// A malformed constant expression.
+    InvalidExpression,
+    /// Overflow or division by zero while folding a constant expression.
+    Arithmetic,
+    /// An instruction, directive, or macro given the wrong number of arguments.
+    WrongArgumentCount,
+    /// A reference to a label or constant that is never defined.
+    UndefinedSymbol,
+    /// A reference to a constant before the line it is defined on.
+    UsedBeforeDefinition,
+    /// A label or constant defined more than once.
+    DuplicateSymbol,
+    /// A label, constant, macro, or parameter name that isn't allowed.
+    InvalidName,
+    /// A directive the assembler doesn't know.
+    UnknownDirective,
+    /// A directive or pseudo-instruction given to `parse_line` that only makes sense in a whole program.
+    OnlyInProgram,
+    /// A problem defining or expanding a macro.
+    Macro,
+    /// An `.include` that can't be found or would include itself.
+    Include,
+    /// Mismatched `.if`, `.else` and `.endif` directives.
+    Conditional,
+    /// A file that can't be read.
+    Io,
+    /// A jump to a literal target outside the program.
+    JumpOutOfRange,
+    /// A jump to a target computed at run time, which can't be checked.
+    ComputedJump,
+    /// An instruction that can never run.
+    Unreachable,
+    /// A `move` of a location to itself.
+    SelfMove,
+    /// A `sub` of zero.
+    SubZero,
+    /// A program that never halts.
+    NoHalt,
+    /// A label that is never used.
+    UnusedLabel,
+    /// A lint name, in `Options` or a directive, that the assembler doesn't know.
+    UnknownLint,
+}
+
+/// How serious a diagnostic is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Severity {
+    /// The program can't be assembled.
+    Error,
+    /// The program can be assembled, but probably doesn't do what was meant.
+    Warning,
+}
+
+/// A problem found while assembling a program, and where it is.
+///
+/// Lines and columns are counted from zero, and columns are byte offsets into the line. The
+/// `Display` implementation shows them counted from one, as editors do: `file:line:column: message`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub kind: ErrorKind,
+    pub severity: Severity,
+    /// The name of the file the problem is in; empty if there is no file, as with `parse_line`.
+    pub file: String,
+    pub line: u64,
+    /// The columns of the line the problem covers.
+    pub columns: Range<usize>,
+    pub message: String,
+    /// What was probably meant instead of the text in `columns`, if the assembler can tell:
+    /// for instance, the closest instruction name to an unknown one.
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic that doesn't yet know where it is.
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        Diagnostic { kind, severity: Severity::Error, file: String::new(), line: 0, columns: 0..0, message: message.into(), suggestion: None }
+    }
+
+    /// Set the columns this diagnostic covers.
+    pub fn with_columns(mut self, columns: Range<usize>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Make this diagnostic a warning rather than an error.
+    pub fn as_warning(mut self) -> Self {
+        self.severity = Severity::Warning;
+        self
+    }
+
+    /// Suggest what was probably meant instead of the text in the columns this diagnostic covers.
+    pub fn with_suggestion<S: Into<String>>(mut self, suggestion: S) -> Self {
+        self.suggestion = Some(suggestion.into());
+        self
+    }
+
+    /// Set the columns this diagnostic covers to the whole of `item`, unless they are already set.
+    /// Helpers that parse a single item use this to point at it.
+    pub fn covering(self, item: &str) -> Self {
+        if self.columns.is_empty() { self.with_columns(0..item.len()) } else { self }
+    }
+
+    /// Move the columns this diagnostic covers along by `offset`; for diagnostics about a piece of
+    /// a line, this makes them relative to the whole line.
+    pub fn shifted(mut self, offset: usize) -> Self {
+        self.columns = self.columns.start + offset..self.columns.end + offset;
+        self
+    }
+
+    /// Make the columns of a diagnostic about `token`, one of the pieces `lex::lex_line` split
+    /// `line` into, relative to the whole line.
+    pub fn within(self, line: &str, token: &str) -> Self {
+        self.covering(token).shifted(lex::offset(line, token))
+    }
+
+    /// Make a diagnostic cover all of `pieces` of `line`, from the start of the first to the end of
+    /// the last; for problems with a whole statement.
+    pub fn spanning(self, line: &str, pieces: &[&str]) -> Self {
+        match (pieces.first(), pieces.last()) {
+            (Some(first), Some(last)) => self.with_columns(lex::offset(line, first)..lex::offset(line, last) + last.len()),
+            _ => self,
+        }
+    }
+
+    /// Set the file and line this diagnostic is on.
+    pub fn at<S: Into<String>>(mut self, file: S, line: u64) -> Self {
+        self.file = file.into();
+        self.line = line;
+        self
+    }
+
+    /// Where this diagnostic is, as `file:line:column` counted from one.
+    pub fn location(&self) -> String {
+        if self.file.is_empty() {
+            format!("{}:{}", self.line + 1, self.columns.start + 1)
+        } else {
+            format!("{}:{}:{}", self.file, self.line + 1, self.columns.start + 1)
+        }
+    }
+
+    /// Render this diagnostic for a person to read, in the style of rustc: the message, where it
+    /// is, and, given `source` (the text of the line it's on), that line with the columns it covers
+    /// underlined, then any suggestion. Diagnostics from lints name the lint they came from.
+    /// If `color` is true, ANSI escape codes are used to highlight the parts.
+    ///
+    /// ```
+    /// use mlem_asm::parse_program;
+    /// let error = &parse_program("move r0 r9").unwrap_err()[0];
+    /// assert_eq!(error.render(Some("move r0 r9"), false), "\
+    /// error: Unknown register name: r9
+    ///  --> <input>:1:9
+    ///   |
+    /// 1 | move r0 r9
+    ///   |         ^^");
+    /// ```
+    pub fn render(&self, source: Option<&str>, color: bool) -> String {
+        let paint = |style: &str, text: &str| if color { format!("{}{}{}", style, text, RESET) } else { text.to_string() };
+        let (label, highlight) = match self.severity {
+            Severity::Error => ("error", ERROR),
+            Severity::Warning => ("warning", WARNING),
+        };
+        // Diagnostics from lints are labelled with the lint's name, so it can be allowed or denied.
+        let label = match Lint::of_kind(self.kind) {
+            Some(lint) => format!("{}[{}]", label, lint.name()),
+            None => label.to_string(),
+        };
+        let number = (self.line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        let mut rendered = format!("{}{}\n{}{} {}",
+                                   paint(highlight, &label), paint(BOLD, &format!(": {}", self.message)),
+                                   gutter, paint(BLUE, "-->"), self.location());
+        if let Some(source) = source {
+            rendered += &format!("\n{} {}\n{} {}", gutter, paint(BLUE, "|"), paint(BLUE, &number), paint(BLUE, "|"));
+            if !source.is_empty() {
+                rendered += &format!(" {}", source);
+            }
+            // Columns past the end of the line, or in the middle of a character, can't be underlined.
+            if let (Some(before), Some(covered)) = (source.get(..self.columns.start), source.get(self.columns.clone())) {
+                if !covered.is_empty() {
+                    // Tabs before the underline are kept, so that it lines up however wide they are.
+                    let padding: String = before.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+                    let underline = "^".repeat(covered.chars().count());
+                    rendered += &format!("\n{} {} {}{}", gutter, paint(BLUE, "|"), padding, paint(highlight, &underline));
+                }
+            }
+        }
+        if let Some(ref suggestion) = self.suggestion {
+            rendered += &format!("\n{} {} {}: did you mean `{}`?", gutter, paint(BLUE, "="), paint(BOLD, "help"), suggestion);
+        }
+        rendered
+    }
+}
+
+// ANSI escape codes for rendering diagnostics in color.
+const ERROR: &str = "\x1b[1;31m";
+const WARNING: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location(), self.message)
+    }
+}
+
+impl Error for Diagnostic {}
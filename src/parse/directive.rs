@@ -0,0 +1,51 @@
+use super::address::parse_value;
+use super::diagnostic::{Diagnostic, ErrorKind};
+use super::label::check_symbol_name;
+use super::lint::Lint;
+use super::symbol::{Location, Symbol, SymbolKind, Symbols};
+
+/// Check whether a lexed line is a directive to the assembler (like `.equ`) rather than an instruction.
+pub fn is_directive(pieces: &[&str]) -> bool {
+    match pieces.first() {
+        Some(first) => first.starts_with('.'),
+        None => false,
+    }
+}
+
+/// Carry out a directive, given its line and the pieces of it (including the directive name).
+pub fn process_directive(line: &str, pieces: &[&str], symbols: &mut Symbols, at: &Location) -> Result<(), Diagnostic> {
+    match pieces[0].to_lowercase().as_str() {
+        ".equ" | ".define" => define_constant(line, pieces, symbols, at),
+        ".allow" | ".warn" | ".deny" => check_lint_setting(line, pieces),
+        ".macro" | ".endm" | ".include" | ".if" | ".ifdef" | ".ifndef" | ".else" | ".endif" => Err(
+            Diagnostic::new(ErrorKind::OnlyInProgram, format!("{} can only be used in a whole program.", pieces[0]))
+                .within(line, pieces[0])
+        ),
+        other => Err(Diagnostic::new(ErrorKind::UnknownDirective, format!("Unknown directive: {}", other)).within(line, pieces[0])),
+    }
+}
+
+/// Handle a directive of the form `.equ NAME value`, binding the name to the value.
+/// The value may be a literal or a previously defined symbol.
+fn define_constant(line: &str, pieces: &[&str], symbols: &mut Symbols, at: &Location) -> Result<(), Diagnostic> {
+    if pieces.len() != 3 {
+        return Err(Diagnostic::new(ErrorKind::WrongArgumentCount,
+                                   format!("Wrong number of arguments to {}. Expected a name and a value; got {}.",
+                                           pieces[0], pieces.len() - 1)).spanning(line, pieces));
+    }
+    let name = pieces[1];
+    check_symbol_name(name).map_err(|e| e.within(line, name))?;
+    let value = parse_value(pieces[2], symbols, at).map_err(|e| e.within(line, pieces[2]))?;
+    symbols.define(name, Symbol { kind: SymbolKind::Constant, value, location: at.clone() }).map_err(|e| e.within(line, name))
+}
+
+/// Check a directive of the form `.allow lint`, `.warn lint` or `.deny lint`. The lint's level is
+/// changed once the program is assembled, when lints are run.
+fn check_lint_setting(line: &str, pieces: &[&str]) -> Result<(), Diagnostic> {
+    if pieces.len() != 2 {
+        return Err(Diagnostic::new(ErrorKind::WrongArgumentCount,
+                                   format!("Wrong number of arguments to {}. Expected the name of a lint; got {}.",
+                                           pieces[0], pieces.len() - 1)).spanning(line, pieces));
+    }
+    Lint::from_name(pieces[1]).map(|_| ()).map_err(|e| e.within(line, pieces[1]))
+}
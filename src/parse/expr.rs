@@ -0,0 +1,278 @@
+use std::ops::Range;
+use super::address::{parse_literal, parse_char_literal};
+use super::diagnostic::{Diagnostic, ErrorKind};
+use super::symbol::{Location, Symbols};
+
+/// A piece of a constant expression.
+#[derive(Debug, PartialEq, Clone)]
+enum ExprToken<'a> {
+    /// A number, in any radix parse_literal accepts.
+    Number(&'a str),
+    /// A character literal, quotes included.
+    Character(&'a str),
+    /// The name of a label or constant.
+    Name(&'a str),
+    /// An operator or parenthesis.
+    Operator(&'static str),
+}
+
+impl<'a> ExprToken<'a> {
+    fn text(&self) -> &'a str {
+        match *self {
+            ExprToken::Number(t) | ExprToken::Character(t) | ExprToken::Name(t) => t,
+            ExprToken::Operator(o) => o,
+        }
+    }
+}
+
+// Longer operators come first, so that `<<` isn't read as two `<`s.
+const OPERATORS: [&str; 18] = ["<<", ">>", "<=", ">=", "==", "!=", "<", ">",
+                               "+", "-", "*", "/", "%", "&", "|", "~", "(", ")"];
+
+/// Split an expression into numbers, names, and operators, each with the column it starts at.
+/// Whitespace is ignored.
+fn tokenize(expression: &str) -> Result<Vec<(usize, ExprToken<'_>)>, Diagnostic> {
+    let mut tokens = Vec::new();
+    let mut rest = expression;
+    while let Some(c) = rest.chars().next() {
+        let start = expression.len() - rest.len();
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push((start, if c.is_ascii_digit() { ExprToken::Number(&rest[..end]) } else { ExprToken::Name(&rest[..end]) }));
+            rest = &rest[end..];
+        } else if c == '\'' {
+            // Find the closing quote, skipping over escaped characters.
+            let mut end = rest.len();
+            let mut escaped = false;
+            for (i, q) in rest.char_indices().skip(1) {
+                if escaped { escaped = false; }
+                else if q == '\\' { escaped = true; }
+                else if q == '\'' { end = i + 1; break; }
+            }
+            tokens.push((start, ExprToken::Character(&rest[..end])));
+            rest = &rest[end..];
+        } else {
+            match OPERATORS.iter().find(|o| rest.starts_with(*o)) {
+                Some(o) => {
+                    tokens.push((start, ExprToken::Operator(o)));
+                    rest = &rest[o.len()..];
+                }
+                None => return Err(Diagnostic::new(ErrorKind::InvalidExpression, format!("Unexpected character in expression: {}", c))
+                                   .with_columns(start..start + c.len_utf8())),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Binary operators, from loosest to tightest binding.
+const PRECEDENCE: [&[&str]; 7] = [&["|"], &["&"], &["==", "!="], &["<", "<=", ">", ">="],
+                                  &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+/// How deeply `~`s and parentheses may nest in an expression, so that evaluating it can't
+/// overflow the stack.
+pub const MAX_NESTING: usize = 256;
+
+/// A recursive descent evaluator over the tokens of an expression.
+struct Evaluator<'a, 's> {
+    tokens: Vec<(usize, ExprToken<'a>)>,
+    position: usize,
+    /// How many `~`s and parentheses the current token is inside.
+    depth: usize,
+    symbols: &'s Symbols,
+    at: &'s Location,
+}
+
+impl<'a, 's> Evaluator<'a, 's> {
+    fn peek(&self) -> Option<&ExprToken<'a>> { self.tokens.get(self.position).map(|t| &t.1) }
+
+    fn next(&mut self) -> Option<ExprToken<'a>> {
+        let token = self.tokens.get(self.position).map(|t| t.1.clone());
+        self.position += 1;
+        token
+    }
+
+    /// The columns of the token before the current one; that is, the one `next` last returned.
+    fn last_columns(&self) -> Range<usize> {
+        match self.tokens.get(self.position - 1) {
+            Some(&(start, ref token)) => start..start + token.text().len(),
+            None => 0..0,
+        }
+    }
+
+    /// Evaluate a chain of binary operators at the given precedence level or tighter ones.
+    fn binary(&mut self, level: usize) -> Result<u64, Diagnostic> {
+        let mut value = self.unary()?;
+        loop {
+            let (operator, tighter) = match self.peek() {
+                Some(&ExprToken::Operator(o)) => match PRECEDENCE.iter().position(|l| l.contains(&o)) {
+                    Some(l) if l >= level => (o, l + 1),
+                    _ => return Ok(value),
+                },
+                _ => return Ok(value),
+            };
+            self.position += 1;
+            let right = self.binary(tighter)?;
+            value = apply(operator, value, right)?;
+        }
+    }
+
+    /// Evaluate what follows a `~` or opening parenthesis at column `start` with `f`, unless that
+    /// would nest too deeply.
+    fn nested<F: FnOnce(&mut Self) -> Result<u64, Diagnostic>>(&mut self, start: usize, f: F) -> Result<u64, Diagnostic> {
+        if self.depth == MAX_NESTING {
+            return Err(Diagnostic::new(ErrorKind::InvalidExpression, format!("Expression nested more than {} deep.", MAX_NESTING))
+                       .with_columns(start..start + 1));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Evaluate a value with any number of `~` operators applied to it.
+    fn unary(&mut self) -> Result<u64, Diagnostic> {
+        let token = self.next();
+        let start = self.last_columns().start;
+        let text = token.as_ref().map(|t| t.text()).unwrap_or("");
+        let result = match token {
+            Some(ExprToken::Operator("~")) => return Ok(!self.nested(start, Self::unary)?),
+            Some(ExprToken::Operator("(")) => return self.nested(start, |e| {
+                let value = e.binary(0)?;
+                match e.next() {
+                    Some(ExprToken::Operator(")")) => Ok(value),
+                    _ => Err(Diagnostic::new(ErrorKind::InvalidExpression, "Unclosed parenthesis in expression.")
+                             .with_columns(start..start + 1)),
+                }
+            }),
+            Some(ExprToken::Number(n)) => parse_literal(n),
+            Some(ExprToken::Character(c)) => parse_char_literal(c),
+            Some(ExprToken::Name(name)) => self.symbols.resolve(name, self.at),
+            Some(ExprToken::Operator(o)) => Err(Diagnostic::new(ErrorKind::InvalidExpression,
+                                                                format!("Expected a value in expression, found {}", o))),
+            None => return Err(Diagnostic::new(ErrorKind::InvalidExpression, "Expected a value, found the end of the expression.")),
+        };
+        // Problems with a single value are about the columns it's in.
+        result.map_err(|e| e.covering(text).shifted(start))
+    }
+}
+
+/// Apply a binary operator, checking for overflow and division by zero.
+fn apply(operator: &str, a: u64, b: u64) -> Result<u64, Diagnostic> {
+    let result = match operator {
+        "+" => a.checked_add(b),
+        "-" => a.checked_sub(b),
+        "*" => a.checked_mul(b),
+        "/" | "%" if b == 0 => return Err(Diagnostic::new(ErrorKind::Arithmetic, format!("Division by zero: {} {} {}", a, operator, b))),
+        "/" => a.checked_div(b),
+        "%" => a.checked_rem(b),
+        // Shifting by 64 or more bits is an overflow, as is shifting set bits off the top.
+        "<<" => if b < 64 && (a << b) >> b == a { Some(a << b) } else { None },
+        ">>" => if b < 64 { Some(a >> b) } else { None },
+        "&" => Some(a & b),
+        "|" => Some(a | b),
+        // Comparisons are 1 if true and 0 if false.
+        "==" => Some((a == b) as u64),
+        "!=" => Some((a != b) as u64),
+        "<" => Some((a < b) as u64),
+        "<=" => Some((a <= b) as u64),
+        ">" => Some((a > b) as u64),
+        ">=" => Some((a >= b) as u64),
+        _ => unreachable!("Unknown operator {}", operator),
+    };
+    match result {
+        Some(v) => Ok(v),
+        None => Err(Diagnostic::new(ErrorKind::Arithmetic, format!("Arithmetic overflow: {} {} {}", a, operator, b))),
+    }
+}
+
+/// Evaluate a constant expression, like `(BASE + 4*2) << 1`, to a value.
+///
+/// Expressions combine literals and symbols with `+ - * / % << >> & |`, the comparisons
+/// `== != < <= > >=` (which give 1 for true and 0 for false), the unary `~` (bitwise not), and
+/// parentheses, following the usual C precedence. All arithmetic is on `u64`s; overflow and
+/// division by zero are errors. `at` is where the expression is used.
+///
+/// The columns of any error are relative to the start of the expression.
+pub fn evaluate(expression: &str, symbols: &Symbols, at: &Location) -> Result<u64, Diagnostic> {
+    let mut evaluator = Evaluator { tokens: tokenize(expression)?, position: 0, depth: 0, symbols, at };
+    let value = match evaluator.binary(0) {
+        Ok(v) => v,
+        Err(e) => return Err(e.covering(expression)),
+    };
+    let result = match evaluator.next() {
+        None => return Ok(value),
+        Some(ExprToken::Operator(")")) => Diagnostic::new(ErrorKind::InvalidExpression, "Unmatched closing parenthesis in expression."),
+        Some(_) => Diagnostic::new(ErrorKind::InvalidExpression, format!("Unexpected trailing input in expression: {}", expression)),
+    };
+    let start = evaluator.last_columns().start;
+    Err(result.with_columns(start..expression.len()))
+}
+
+#[cfg(test)]
+mod test_expr {
+    use super::*;
+    use super::super::symbol::{Symbol, SymbolKind};
+
+    fn at(position: u64) -> Location {
+        Location { file: String::new(), line: position, position }
+    }
+
+    #[test]
+    fn test_precedence() {
+        let symbols = Symbols::new();
+        assert_eq!(evaluate("1 + 2 * 3", &symbols, &at(0)), Ok(7));
+        assert_eq!(evaluate("(1 + 2) * 3", &symbols, &at(0)), Ok(9));
+        assert_eq!(evaluate("1 << 4 | 0x3 & 0b10", &symbols, &at(0)), Ok(18));
+        assert_eq!(evaluate("~0 >> 60", &symbols, &at(0)), Ok(15));
+        assert_eq!(evaluate("17 % 5 - 1", &symbols, &at(0)), Ok(1));
+        assert_eq!(evaluate("'a' - 'A'", &symbols, &at(0)), Ok(32));
+        assert_eq!(evaluate("1 << 2 == 4 & 3 > 2", &symbols, &at(0)), Ok(1));
+        assert_eq!(evaluate("2 <= 1 | 5 != 5", &symbols, &at(0)), Ok(0));
+    }
+
+    fn error(kind: ErrorKind, message: &str, columns: Range<usize>) -> Result<u64, Diagnostic> {
+        Err(Diagnostic::new(kind, message).with_columns(columns))
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut symbols = Symbols::new();
+        symbols.define("BASE", Symbol { kind: SymbolKind::Constant, value: 10, location: at(0) }).unwrap();
+        assert_eq!(evaluate("(BASE + 4*2)", &symbols, &at(1)), Ok(18));
+        assert_eq!(evaluate("BASE-1", &symbols, &at(1)), Ok(9));
+        assert_eq!(evaluate("1 + OTHER", &symbols, &at(1)), error(ErrorKind::UndefinedSymbol, "Undefined symbol: OTHER", 4..9));
+    }
+
+    #[test]
+    fn test_errors() {
+        let symbols = Symbols::new();
+        assert_eq!(evaluate("1 / (2 - 2)", &symbols, &at(0)), error(ErrorKind::Arithmetic, "Division by zero: 1 / 0", 0..11));
+        assert_eq!(evaluate("0 - 1", &symbols, &at(0)), error(ErrorKind::Arithmetic, "Arithmetic overflow: 0 - 1", 0..5));
+        assert_eq!(evaluate("1 << 64", &symbols, &at(0)), error(ErrorKind::Arithmetic, "Arithmetic overflow: 1 << 64", 0..7));
+        assert_eq!(evaluate("2 * (1 + 2", &symbols, &at(0)),
+                   error(ErrorKind::InvalidExpression, "Unclosed parenthesis in expression.", 4..5));
+        assert_eq!(evaluate("1 + 2) - 1", &symbols, &at(0)),
+                   error(ErrorKind::InvalidExpression, "Unmatched closing parenthesis in expression.", 5..10));
+        assert_eq!(evaluate("1 +", &symbols, &at(0)),
+                   error(ErrorKind::InvalidExpression, "Expected a value, found the end of the expression.", 0..3));
+        assert_eq!(evaluate("1 + 0xg", &symbols, &at(0)).unwrap_err().columns, 4..7);
+        assert_eq!(evaluate("1 $ 2", &symbols, &at(0)),
+                   error(ErrorKind::InvalidExpression, "Unexpected character in expression: $", 2..3));
+    }
+
+    #[test]
+    fn test_nesting() {
+        let symbols = Symbols::new();
+        let nots = |n| "~".repeat(n) + "0";
+        let parentheses = |n| "(".repeat(n) + "1" + &")".repeat(n);
+        assert_eq!(evaluate(&nots(MAX_NESTING), &symbols, &at(0)), Ok(0));
+        assert_eq!(evaluate(&parentheses(MAX_NESTING), &symbols, &at(0)), Ok(1));
+        let too_deep = |column| error(ErrorKind::InvalidExpression, "Expression nested more than 256 deep.", column..column + 1);
+        assert_eq!(evaluate(&nots(200_000), &symbols, &at(0)), too_deep(MAX_NESTING));
+        assert_eq!(evaluate(&parentheses(20_000), &symbols, &at(0)), too_deep(MAX_NESTING));
+        assert_eq!(evaluate(&("1 + ~(".repeat(200) + "0" + &")".repeat(200)), &symbols, &at(0)), too_deep(MAX_NESTING / 2 * 6 + 4));
+    }
+}
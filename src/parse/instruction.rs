@@ -1,5 +1,9 @@
 #![allow(dead_code)]
+use super::diagnostic::{Diagnostic, ErrorKind};
+use super::suggest::suggest;
+use super::pseudo::PSEUDO_INSTRUCTIONS;
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum InstructionName {
     NoOp,
     Zero,
@@ -18,23 +22,82 @@ pub enum InstructionName {
     None,
 }
 
-pub fn parse_instruction(name: &str) -> Result<InstructionName, String> {
-    use std::borrow::Borrow;
-    match name.to_lowercase().borrow() {
-        "noop" => Ok(InstructionName::NoOp),
-        "halt" => Ok(InstructionName::Halt),
-        "illegal" | "illeg" | "illg" => Ok(InstructionName::Illegal),
-        "zero" => Ok(InstructionName::Zero),
-        "move" => Ok(InstructionName::Move),
-        "output" | "oput" => Ok(InstructionName::Output),
-        "input" | "iput" => Ok(InstructionName::Input),
-        "add" | "uadd" => Ok(InstructionName::Add),
-        "subtract" | "usub" | "sub" => Ok(InstructionName::Sub),
-        "jump" => Ok(InstructionName::Jump),
-        "jumpifzero" | "jumpzero" | "jz" => Ok(InstructionName::JumpIfZero),
-        "jumpnotzer" | "jumpnotzero" | "jnz" => Ok(InstructionName::JumpNotZero),
-        "push" => Ok(InstructionName::Push),
-        "pop" => Ok(InstructionName::Pop),
-        other => Err(format!("Unknown instruction: {}", other))
+/// What an instruction does with one of its operands.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operand {
+    /// The operand is only read, so it can be any address, including a literal.
+    Source,
+    /// The operand is written to, so it can't be a literal.
+    Destination,
+}
+
+impl InstructionName {
+    /// The canonical name of the instruction, out of all of its aliases, as the formatter writes it.
+    pub fn mnemonic(&self) -> Option<&'static str> {
+        match *self {
+            InstructionName::NoOp => Some("noop"),
+            InstructionName::Zero => Some("zero"),
+            InstructionName::Move => Some("move"),
+            InstructionName::Output => Some("output"),
+            InstructionName::Input => Some("input"),
+            InstructionName::Add => Some("add"),
+            InstructionName::Sub => Some("sub"),
+            InstructionName::Halt => Some("halt"),
+            InstructionName::Illegal => Some("illegal"),
+            InstructionName::Jump => Some("jump"),
+            InstructionName::JumpIfZero => Some("jz"),
+            InstructionName::JumpNotZero => Some("jnz"),
+            InstructionName::Push => Some("push"),
+            InstructionName::Pop => Some("pop"),
+            InstructionName::None => None,
+        }
+    }
+
+    /// The operands the instruction takes, in order.
+    pub fn operands(&self) -> &'static [Operand] {
+        use self::Operand::*;
+        match *self {
+            InstructionName::NoOp | InstructionName::Halt | InstructionName::Illegal | InstructionName::None => &[],
+            InstructionName::Zero | InstructionName::Input | InstructionName::Pop => &[Destination],
+            InstructionName::Output | InstructionName::Jump | InstructionName::Push => &[Source],
+            InstructionName::Move => &[Source, Destination],
+            // Arithmetic stores its result in the first operand.
+            InstructionName::Add | InstructionName::Sub => &[Destination, Source],
+            // Conditional jumps take the target, then the value to test.
+            InstructionName::JumpIfZero | InstructionName::JumpNotZero => &[Source, Source],
+        }
+    }
+}
+
+/// Every name an instruction can be written with, including aliases, with the instruction it names.
+pub const MNEMONICS: [(&str, InstructionName); 25] = [
+    ("noop", InstructionName::NoOp),
+    ("halt", InstructionName::Halt),
+    ("illegal", InstructionName::Illegal), ("illeg", InstructionName::Illegal), ("illg", InstructionName::Illegal),
+    ("zero", InstructionName::Zero),
+    ("move", InstructionName::Move),
+    ("output", InstructionName::Output), ("oput", InstructionName::Output),
+    ("input", InstructionName::Input), ("iput", InstructionName::Input),
+    ("add", InstructionName::Add), ("uadd", InstructionName::Add),
+    ("subtract", InstructionName::Sub), ("usub", InstructionName::Sub), ("sub", InstructionName::Sub),
+    ("jump", InstructionName::Jump),
+    ("jumpifzero", InstructionName::JumpIfZero), ("jumpzero", InstructionName::JumpIfZero), ("jz", InstructionName::JumpIfZero),
+    ("jumpnotzer", InstructionName::JumpNotZero), ("jumpnotzero", InstructionName::JumpNotZero), ("jnz", InstructionName::JumpNotZero),
+    ("push", InstructionName::Push),
+    ("pop", InstructionName::Pop),
+];
+
+/// Parse the name of an instruction (case insensitive). If it isn't one, the error suggests the
+/// closest name that is, including pseudo-instructions.
+pub fn parse_instruction(name: &str) -> Result<InstructionName, Diagnostic> {
+    let lowercase = name.to_lowercase();
+    if let Some(&(_, instruction)) = MNEMONICS.iter().find(|m| m.0 == lowercase) {
+        return Ok(instruction);
+    }
+    let error = Diagnostic::new(ErrorKind::UnknownInstruction, format!("Unknown instruction: {}", lowercase)).covering(name);
+    let names: Vec<&str> = MNEMONICS.iter().map(|m| m.0).chain(PSEUDO_INSTRUCTIONS.iter().cloned()).collect();
+    match suggest(&lowercase, &names) {
+        Some(s) => Err(error.with_suggestion(s)),
+        None => Err(error),
     }
 }
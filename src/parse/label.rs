@@ -0,0 +1,76 @@
+use super::address::parse_register_name;
+use super::diagnostic::{Diagnostic, ErrorKind};
+
+/// If the given token defines a label (a name followed by a colon, like `loop:`),
+/// return the name being defined.
+pub fn label_definition(token: &str) -> Option<&str> {
+    if token.len() > 1 && token.ends_with(':') {
+        Some(&token[..token.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// Check whether a name could refer to a symbol: a letter or underscore followed by
+/// any number of letters, digits, or underscores.
+pub fn is_identifier(name: &str) -> bool {
+    let mut characters = name.chars();
+    match characters.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            characters.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Check that a name is allowed to be defined as a label or constant.
+/// Names must be identifiers, and may not shadow register names.
+pub fn check_symbol_name(name: &str) -> Result<(), Diagnostic> {
+    if !is_identifier(name) {
+        Err(Diagnostic::new(ErrorKind::InvalidName,
+                            format!("Invalid symbol name: {} (names must start with a letter or underscore)", name)).covering(name))
+    } else if parse_register_name(name).is_ok() {
+        Err(Diagnostic::new(ErrorKind::InvalidName, format!("Invalid symbol name: {} is a register name", name)).covering(name))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether a name ends as the names `\@` makes in macro expansions do: in `__` and a number.
+pub fn is_expansion_name(name: &str) -> bool {
+    match name.rfind("__") {
+        Some(i) => name.len() > i + 2 && name[i + 2..].chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Check that a name written in the source, rather than made by a macro expansion, doesn't end as
+/// the names `\@` makes do, which it could collide with.
+pub fn check_not_expansion_name(name: &str) -> Result<(), Diagnostic> {
+    if is_expansion_name(name) {
+        Err(Diagnostic::new(ErrorKind::InvalidName,
+                            format!("Invalid symbol name: {} (names ending in __ and a number are kept for macro expansions)", name))
+            .covering(name))
+    } else {
+        Ok(())
+    }
+}
+
+/// Split the label definitions off the front of a lexed line, returning the names defined
+/// and the remaining pieces. Label definitions must come before the instruction.
+/// `line` is the line the pieces were lexed from, so that errors can say where they are.
+pub fn split_labels<'a>(line: &str, pieces: &[&'a str]) -> Result<(Vec<&'a str>, Vec<&'a str>), Diagnostic> {
+    let mut names = Vec::new();
+    let mut rest = pieces;
+    while let Some((first, tail)) = rest.split_first() {
+        match label_definition(first) {
+            Some(name) => {
+                check_symbol_name(name).map_err(|e| e.within(line, first))?;
+                names.push(name);
+                rest = tail;
+            }
+            None => break,
+        }
+    }
+    Ok((names, rest.to_vec()))
+}
@@ -0,0 +1,195 @@
+use super::lex;
+use super::check::Finding;
+use super::diagnostic::{Diagnostic, ErrorKind};
+use super::label::split_labels;
+use super::preprocess::{PositionedError, SourceLine};
+use super::suggest::suggest;
+
+/// A check for something in a program that is probably a mistake, but doesn't stop it from being
+/// assembled. Each lint can be allowed, made a warning, or denied (made an error), from `Options`
+/// or with the `.allow`, `.warn` and `.deny` directives in the program.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Lint {
+    /// A jump to a literal target outside the program.
+    JumpOutOfRange,
+    /// A jump to a target computed at run time, which can't be checked.
+    ComputedJump,
+    /// An instruction after a `halt` or `jump` that no jump or label leads to.
+    Unreachable,
+    /// A `move` of a location to itself.
+    SelfMove,
+    /// A `sub` of the literal zero.
+    SubZero,
+    /// A program without any `halt` instruction.
+    NoHalt,
+    /// A label that is never used.
+    UnusedLabel,
+}
+
+/// All of the lints, in the order they're checked.
+pub const LINTS: [Lint; 7] = [
+    Lint::JumpOutOfRange, Lint::ComputedJump, Lint::Unreachable, Lint::SelfMove, Lint::SubZero, Lint::NoHalt, Lint::UnusedLabel,
+];
+
+/// What to do when a lint finds something.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Level {
+    /// Ignore it.
+    Allow,
+    /// Report it as a warning, and assemble the program anyway.
+    Warn,
+    /// Report it as an error.
+    Deny,
+}
+
+impl Lint {
+    /// The name of this lint, as used on the command line and in directives.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Lint::JumpOutOfRange => "jump-out-of-range",
+            Lint::ComputedJump => "computed-jump",
+            Lint::Unreachable => "unreachable",
+            Lint::SelfMove => "self-move",
+            Lint::SubZero => "sub-zero",
+            Lint::NoHalt => "no-halt",
+            Lint::UnusedLabel => "unused-label",
+        }
+    }
+
+    /// A short description of what this lint finds.
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Lint::JumpOutOfRange => "jumps to literal targets outside the program",
+            Lint::ComputedJump => "jumps to targets in registers or memory, which can't be checked",
+            Lint::Unreachable => "instructions after a halt or jump that nothing leads to",
+            Lint::SelfMove => "moves of a location to itself",
+            Lint::SubZero => "subtractions of zero",
+            Lint::NoHalt => "programs with no halt instruction",
+            Lint::UnusedLabel => "labels that are never used",
+        }
+    }
+
+    /// What is done when this lint finds something, unless it is configured otherwise.
+    /// Jumps outside the program are certain to fault, so they are errors.
+    pub fn default_level(&self) -> Level {
+        match *self {
+            Lint::JumpOutOfRange => Level::Deny,
+            _ => Level::Warn,
+        }
+    }
+
+    /// The kind of the diagnostics this lint reports.
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            Lint::JumpOutOfRange => ErrorKind::JumpOutOfRange,
+            Lint::ComputedJump => ErrorKind::ComputedJump,
+            Lint::Unreachable => ErrorKind::Unreachable,
+            Lint::SelfMove => ErrorKind::SelfMove,
+            Lint::SubZero => ErrorKind::SubZero,
+            Lint::NoHalt => ErrorKind::NoHalt,
+            Lint::UnusedLabel => ErrorKind::UnusedLabel,
+        }
+    }
+
+    /// Find the lint that reports diagnostics of the given kind, if any does.
+    pub fn of_kind(kind: ErrorKind) -> Option<Lint> {
+        LINTS.iter().find(|l| l.kind() == kind).cloned()
+    }
+
+    /// Find a lint by name, suggesting the closest name if there is none by that name.
+    pub fn from_name(name: &str) -> Result<Lint, Diagnostic> {
+        if let Some(lint) = LINTS.iter().find(|l| l.name() == name) {
+            return Ok(*lint);
+        }
+        let names: Vec<&str> = LINTS.iter().map(|l| l.name()).collect();
+        let error = Diagnostic::new(ErrorKind::UnknownLint, format!("Unknown lint: {}", name)).covering(name);
+        Err(match suggest(name, &names) {
+            Some(s) => error.with_suggestion(s),
+            None => error,
+        })
+    }
+}
+
+impl Level {
+    /// Find the level set by a directive (`.allow`, `.warn` or `.deny`), if it is one of those.
+    pub fn of_directive(directive: &str) -> Option<Level> {
+        match directive.to_lowercase().as_str() {
+            ".allow" => Some(Level::Allow),
+            ".warn" => Some(Level::Warn),
+            ".deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// The level of every lint at some point in a program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Levels {
+    levels: Vec<(Lint, Level)>,
+}
+
+impl Levels {
+    /// The default levels of every lint, changed by each of `settings` in turn.
+    pub fn new(settings: &[(Lint, Level)]) -> Self {
+        let mut levels = Levels { levels: LINTS.iter().map(|l| (*l, l.default_level())).collect() };
+        for &(lint, level) in settings {
+            levels.set(lint, level);
+        }
+        levels
+    }
+
+    pub fn get(&self, lint: Lint) -> Level {
+        self.levels.iter().find(|&&(l, _)| l == lint).map_or(lint.default_level(), |&(_, level)| level)
+    }
+
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        for entry in &mut self.levels {
+            if entry.0 == lint {
+                entry.1 = level;
+            }
+        }
+    }
+
+    /// Report something a lint found at this level: as an error, a warning, or not at all.
+    pub fn apply(&self, lint: Lint, diagnostic: Diagnostic) -> Option<Diagnostic> {
+        match self.get(lint) {
+            Level::Allow => None,
+            Level::Warn => Some(diagnostic.as_warning()),
+            Level::Deny => Some(diagnostic),
+        }
+    }
+}
+
+/// Decide how to report what the lints found in a program, as errors or warnings (with the
+/// position each is at), or not at all. Lints start at the levels in `settings`, and the `.allow`,
+/// `.warn` and `.deny` directives in `lines` change them for the rest of the program.
+pub fn report(mut findings: Vec<Finding>, lines: &[SourceLine], settings: &[(Lint, Level)]) -> Vec<PositionedError> {
+    findings.sort_by_key(|f| f.0);
+    let mut findings = findings.into_iter().peekable();
+    let mut levels = Levels::new(settings);
+    let mut reported = Vec::new();
+    for position in 0..lines.len() + 1 {
+        if let Some(line) = lines.get(position) {
+            if let Some((lint, level)) = pragma(&line.text) {
+                levels.set(lint, level);
+            }
+        }
+        while findings.peek().is_some_and(|f| f.0 == position) {
+            let (_, lint, diagnostic) = findings.next().unwrap();
+            if let Some(diagnostic) = levels.apply(lint, diagnostic) {
+                reported.push((position as u64, diagnostic));
+            }
+        }
+    }
+    reported
+}
+
+/// The lint and level set by a line, if it is a valid `.allow`, `.warn` or `.deny` directive.
+fn pragma(line: &str) -> Option<(Lint, Level)> {
+    let (_, pieces) = split_labels(line, &lex::lex_line(line)).ok()?;
+    let level = Level::of_directive(pieces.first()?)?;
+    match pieces.len() {
+        2 => Lint::from_name(pieces[1]).ok().map(|lint| (lint, level)),
+        _ => None,
+    }
+}
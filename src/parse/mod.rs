@@ -1,15 +1,99 @@
-use mlem::{Instruction, Program};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use mlem::{Address, Instruction, Program};
 use super::lex;
 mod address;
-use self::address::parse_address;
+pub use self::address::{parse_address, parse_register_name};
+mod diagnostic;
+pub use self::diagnostic::{Diagnostic, ErrorKind, Severity};
 mod instruction;
-use self::instruction::{InstructionName, parse_instruction};
+use self::instruction::{InstructionName, Operand, parse_instruction};
+mod label;
+pub use self::label::label_definition;
+use self::label::split_labels;
+mod symbol;
+pub use self::symbol::{Location, Symbol, SymbolKind, Symbols};
+mod directive;
+mod expr;
+use self::directive::{is_directive, process_directive};
+mod pseudo;
+mod suggest;
+use self::pseudo::{is_pseudo_instruction, expanded_length};
+mod preprocess;
+use self::preprocess::{Preprocessor, SourceLine};
+mod check;
+use self::check::{lint_program, Origin};
+pub(crate) use self::check::jump_target;
+mod lint;
+pub use self::lint::{Lint, Level, LINTS};
 #[cfg(test)]
 mod test;
 
+/// The file name given to programs that don't come from a file, as with `parse_program`.
+pub const INPUT_NAME: &str = "<input>";
+
+/// The file name given to constants defined in `Options`, as from the command line.
+pub const DEFINES_NAME: &str = "<command line>";
+
+/// Settings that control how a program is assembled.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// Directories to search for files named in `.include` directives, in order, after the
+    /// directory of the including file.
+    pub include_paths: Vec<PathBuf>,
+    /// Constants to define before the program starts, as if with `.equ`. These can be used
+    /// in conditions, so that one program can be assembled in several variants.
+    pub defines: Vec<(String, u64)>,
+    /// Levels for lints other than their defaults, in order; later settings override earlier
+    /// ones. The `.allow`, `.warn` and `.deny` directives override these for the rest of the program.
+    pub lints: Vec<(Lint, Level)>,
+}
+
+impl Options {
+    /// Add a constant definition of the form `NAME=value`, where the value is any constant
+    /// expression, or just `NAME`, which defines it as 1.
+    ///
+    /// ```
+    /// use mlem_asm::Options;
+    /// let mut options = Options::default();
+    /// options.define("DEBUG").unwrap();
+    /// options.define("LEVEL=0x2").unwrap();
+    /// assert_eq!(options.defines, vec![("DEBUG".to_string(), 1), ("LEVEL".to_string(), 2)]);
+    /// ```
+    ///
+    /// Errors are given in the file `DEFINES_NAME`, with columns into the definition.
+    pub fn define(&mut self, definition: &str) -> Result<(), Diagnostic> {
+        let (name, value) = match definition.find('=') {
+            Some(i) => match expr::evaluate(&definition[i + 1..], &Symbols::new(), &Location::default()) {
+                Ok(v) => (&definition[..i], v),
+                Err(e) => return Err(e.shifted(i + 1).at(DEFINES_NAME, 0)),
+            },
+            None => (definition, 1),
+        };
+        label::check_symbol_name(name).and_then(|_| label::check_not_expansion_name(name)).map_err(|e| e.at(DEFINES_NAME, 0))?;
+        self.defines.push((name.to_string(), value));
+        Ok(())
+    }
+
+    /// Set the level of the named lint.
+    ///
+    /// ```
+    /// use mlem_asm::{Options, Lint, Level};
+    /// let mut options = Options::default();
+    /// options.set_lint("unused-label", Level::Deny).unwrap();
+    /// assert_eq!(options.lints, vec![(Lint::UnusedLabel, Level::Deny)]);
+    /// assert_eq!(options.set_lint("unused-lable", Level::Allow).unwrap_err().suggestion, Some("unused-label".into()));
+    /// ```
+    pub fn set_lint(&mut self, name: &str, level: Level) -> Result<(), Diagnostic> {
+        self.lints.push((Lint::from_name(name)?, level));
+        Ok(())
+    }
+}
+
 /// Parse a line of the form `instruction [operand1] [operand2] [operand3][;[comment text]]`
 ///
-/// The return value is a `Result<Option<Instruction>, String>`. An `Ok(Some(_))` value means a valid
+/// The return value is a `Result<Option<Instruction>, Diagnostic>`. An `Ok(Some(_))` value means a valid
 /// instruction (for instance, the line `move R:R0 R:R1`). An `Err(_)` value means that there is
 /// unparsable about the line (like `move R:R0 R:r1 garbage garbage`); an `Ok(None)` value means that
 /// the line was legal but meant nothing (like `; comment only`).
@@ -23,24 +107,122 @@ mod test;
 /// assert!(parse_line("noop") == Ok(Some(Instruction::NoOp)));
 /// assert!(parse_line("") == Ok(None));
 /// ```
-pub fn parse_line(line: &str) -> Result<Option<Instruction>, String> {
-    // Split into "words"
-    let pieces: Vec<_> = lex::lex_line(line);
+///
+/// Label definitions at the start of the line are allowed, but since a single line has no
+/// context, references to symbols defined elsewhere are errors; use `parse_program` for that.
+///
+/// ```
+/// use mlem_asm::Instruction;
+/// use mlem_asm::parse::{parse_line, ErrorKind};
+/// assert!(parse_line("loop: noop") == Ok(Some(Instruction::NoOp)));
+/// let error = parse_line("jump loop").unwrap_err();
+/// assert_eq!(error.kind, ErrorKind::UndefinedSymbol);
+/// assert_eq!(error.message, "Undefined symbol: loop");
+/// assert_eq!(error.columns, 5..9);
+/// ```
+///
+/// Pseudo-instructions like `print` that expand to more than one instruction can only be used in
+/// whole programs.
+pub fn parse_line(line: &str) -> Result<Option<Instruction>, Diagnostic> {
+    let mut symbols = Symbols::new();
+    let at = Location::default();
+    let pieces = first_pass_line(line, &at, 0, true, &mut symbols)?;
+    let mut instructions = parse_statement(line, &pieces, &symbols, &at)?;
+    match instructions.len() {
+        0 => Ok(None),
+        1 => Ok(instructions.pop()),
+        n => Err(Diagnostic::new(ErrorKind::OnlyInProgram,
+                                 format!("{} expands to {} instructions; it can only be used in a program.", pieces[0], n))
+                 .spanning(line, &pieces)),
+    }
+}
+
+/// Find the canonical name of an instruction or pseudo-instruction, given any of its names in any
+/// case: `SUBTRACT` and `usub` are both `sub`. Anything else, like a macro, has none.
+///
+/// ```
+/// use mlem_asm::parse::canonical_instruction_name;
+/// assert_eq!(canonical_instruction_name("JumpIfZero"), Some("jz"));
+/// assert_eq!(canonical_instruction_name("PRINT"), Some("print"));
+/// assert_eq!(canonical_instruction_name("countdown"), None);
+/// ```
+pub fn canonical_instruction_name(name: &str) -> Option<&'static str> {
+    match parse_instruction(name) {
+        Ok(instruction) => instruction.mnemonic(),
+        Err(_) => pseudo::PSEUDO_INSTRUCTIONS.iter().find(|p| p.eq_ignore_ascii_case(name)).cloned(),
+    }
+}
+
+/// Take care of everything on a line that has to happen before instructions can be parsed:
+/// labels are defined (marking `instruction_index`) and directives are carried out.
+/// `written` is whether the line was written in the source, rather than made by a macro expansion.
+/// Returns the pieces of the instruction left on the line, if any.
+fn first_pass_line<'a>(line: &'a str, at: &Location, instruction_index: u64, written: bool, symbols: &mut Symbols)
+                       -> Result<Vec<&'a str>, Diagnostic> {
+    if let Some((column, opening)) = lex::find_unclosed(line) {
+        // A quote takes in the rest of the line; a parenthesis, the rest up to the comment.
+        let rest = line[column..].split(['\n', '\r']).next().unwrap_or("");
+        let (kind, what, text) = match opening {
+            '(' => (ErrorKind::InvalidExpression, "Unclosed parenthesis", rest.split(';').next().unwrap_or("")),
+            '"' => (ErrorKind::InvalidLiteral, "Unterminated string literal", rest),
+            _ => (ErrorKind::InvalidLiteral, "Unterminated character literal", rest),
+        };
+        return Err(Diagnostic::new(kind, format!("{}: {}", what, text.trim_end())).with_columns(column..column + 1));
+    }
+    // Split into "words", taking off any label definitions
+    let (labels, pieces) = split_labels(line, &lex::lex_line(line))?;
+    if written {
+        // Names made by `\@` can only be defined by macro expansions.
+        let constant = match pieces.first().map(|p| p.to_lowercase()).as_deref() {
+            Some(".equ") | Some(".define") => pieces.get(1),
+            _ => None,
+        };
+        for name in labels.iter().chain(constant) {
+            label::check_not_expansion_name(name).map_err(|e| e.within(line, name))?;
+        }
+    }
+    for name in labels {
+        symbols.define(name, Symbol { kind: SymbolKind::Label, value: instruction_index, location: at.clone() })
+            .map_err(|e| e.within(line, name))?;
+    }
 
+    if is_directive(&pieces) {
+        process_directive(line, &pieces, symbols, at)?;
+        return Ok(Vec::new());
+    }
+    Ok(pieces)
+}
+
+/// Work out how many instructions the pieces of a line will assemble to.
+fn statement_length(pieces: &[&str]) -> u64 {
+    if pieces.is_empty() { 0 }
+    else if is_pseudo_instruction(pieces) { expanded_length(pieces) }
+    else { 1 }
+}
+
+/// Parse the pieces of a line at `at` into the instructions they stand for, expanding
+/// pseudo-instructions.
+fn parse_statement(line: &str, pieces: &[&str], symbols: &Symbols, at: &Location) -> Result<Vec<Instruction>, Diagnostic> {
+    if is_pseudo_instruction(pieces) {
+        pseudo::expand(line, pieces, symbols, at)
+    } else {
+        Ok(parse_pieces(line, pieces, symbols, at)?.into_iter().collect())
+    }
+}
+
+/// Parse the pieces of an instruction at `at` into an Instruction, resolving any symbols it uses.
+fn parse_pieces(line: &str, pieces: &[&str], symbols: &Symbols, at: &Location) -> Result<Option<Instruction>, Diagnostic> {
     // If there are no words, this line is useless.
-    if pieces.len() == 0 { return Ok(None); }
+    if pieces.is_empty() { return Ok(None); }
 
-    let mut instruction_name = InstructionName::None;
     let mut arg1 = None;
     let mut arg2 = None;
-    let mut arg3 = None;
     // Parse the name of the instruction.
-    if pieces.len() >= 1 {
-        match parse_instruction(pieces[0]) {
-            Ok(v) => { instruction_name = v; },
-            Err(e) => { return Err(e); }
-        };
-    }
+    let instruction_name = match parse_instruction(pieces[0]) {
+        Ok(v) => v,
+        Err(e) => { return Err(e.within(line, pieces[0])); }
+    };
+    let wrong_arguments = |message: &str| Err(Diagnostic::new(ErrorKind::WrongArgumentCount, message).spanning(line, pieces));
 
     // A single piece means a no-arg instruction; it can go straight to an Instruction.
     if pieces.len() == 1 {
@@ -48,28 +230,34 @@ pub fn parse_line(line: &str) -> Result<Option<Instruction>, String> {
             InstructionName::NoOp => Ok(Some(Instruction::NoOp)),
             InstructionName::Halt => Ok(Some(Instruction::Halt)),
             InstructionName::Illegal => Ok(Some(Instruction::Illegal)),
-            _ => Err("Wrong number of arguments. Got 0.".into())
+            _ => wrong_arguments("Wrong number of arguments. Got 0.")
         };
     }
 
     // More than one word means the args need parsed.
     if pieces.len() >= 2 {
-        match parse_address(pieces[1].trim()) {
+        match parse_address(pieces[1].trim(), symbols, at) {
             Ok(v) => { arg1 = Some(v); },
-            Err(e) => { return Err(e); }
+            Err(e) => { return Err(e.within(line, pieces[1].trim())); }
         };
     }
     if pieces.len() >= 3 {
-        match parse_address(pieces[2].trim()) {
+        match parse_address(pieces[2].trim(), symbols, at) {
             Ok(v) => { arg2 = Some(v); },
-            Err(e) => { return Err(e); }
+            Err(e) => { return Err(e.within(line, pieces[2].trim())); }
         };
     }
-    if pieces.len() >= 4 {
-        match parse_address(pieces[3].trim()) {
-            Ok(v) => { arg3 = Some(v); },
-            Err(e) => { return Err(e); }
-        };
+
+    // Literals can't be written to, so they can't be destinations.
+    let operands = instruction_name.operands();
+    if operands.len() == pieces.len() - 1 {
+        for (i, arg) in [arg1, arg2].iter().enumerate() {
+            if let (Some(Address::Literal(_)), Some(&Operand::Destination)) = (*arg, operands.get(i)) {
+                let message = format!("Operand {} of {} is written to, so it can't be the literal {}.",
+                                      i + 1, pieces[0].to_lowercase(), pieces[i + 1]);
+                return Err(Diagnostic::new(ErrorKind::InvalidOperand, message).within(line, pieces[i + 1].trim()));
+            }
+        }
     }
 
     // Single argument instruction
@@ -83,7 +271,7 @@ pub fn parse_line(line: &str) -> Result<Option<Instruction>, String> {
             InstructionName::Jump => Ok(Some(Instruction::Jump(arg1))),
             InstructionName::Push => Ok(Some(Instruction::Push(arg1))),
             InstructionName::Pop => Ok(Some(Instruction::Pop(arg1))),
-            _ => Err("Wrong number of arguments. Got 1.".into())
+            _ => wrong_arguments("Wrong number of arguments. Got 1.")
         };
     }
 
@@ -98,29 +286,59 @@ pub fn parse_line(line: &str) -> Result<Option<Instruction>, String> {
             InstructionName::Sub => Ok(Some(Instruction::Sub(arg1, arg2))),
             InstructionName::JumpIfZero => Ok(Some(Instruction::JumpIfZero(arg1, arg2))),
             InstructionName::JumpNotZero => Ok(Some(Instruction::JumpNotZero(arg1, arg2))),
-            _ => Err("Wrong number of arguments. Got 2.".into())
+            _ => wrong_arguments("Wrong number of arguments. Got 2.")
         }
     }
 
-    Err("Malformed. Perhaps there are too many terms?".into())
+    wrong_arguments("Malformed. Perhaps there are too many terms?")
 }
 
 /// Simply parse a program, each line resulting in either a valid or invalid line (Ok or Err).
+/// Valid lines give the pieces of the instruction they contain (which may be none); along the way,
+/// every label and constant in the program is defined in `symbols`.
 /// This function can't fail; however, there's no guarantee that even one valid instruction is produced.
-fn initial_parse_program(program: &str) -> Vec<Result<Option<Instruction>, String>> {
-    let lines = program.lines();
+fn initial_parse_program<'a>(lines: &'a [SourceLine], symbols: &mut Symbols) -> Vec<Result<Vec<&'a str>, Diagnostic>> {
     let mut v = Vec::new();
-    for line in lines {
-        match parse_line(line) {
-            Ok(i) => { v.push(Ok(i)); }
-            Err(e) => { v.push(Err(e)); }
+    let mut instruction_index = 0;
+    for (position, line) in lines.iter().enumerate() {
+        let result = first_pass_line(&line.text, &line.location(position as u64), instruction_index, line.expansions.is_empty(), symbols);
+        if let Ok(ref pieces) = result {
+            instruction_index += statement_length(pieces);
         }
+        v.push(result);
     }
     v
 }
 
 /// Parse an entire program, returning either a ready-to-execute MLeM program or
-/// a Vec of error messages, with line numbers, of all errors in the program.
+/// a Vec of diagnostics describing all errors in the program, in the order they occur.
+///
+/// Lines may begin with label definitions like `loop:`. A label marks the next instruction in
+/// the program, and its name can be used in place of any literal (most usefully, as the target
+/// of a jump) anywhere in the program, including before it is defined.
+///
+/// Named constants are defined with `.equ NAME value` (or `.define NAME value`), and can likewise
+/// be used in place of any literal, including in memory addresses like `*NAME`; unlike labels, they
+/// must be defined before they are used.
+///
+/// The pseudo-instruction `print "text" scratch` expands into a `move` of each character of
+/// the string into the scratch location, followed by an `output` of it.
+///
+/// Macros are defined with `.macro name param1 param2 ...`, a body, and `.endm`, and invoked like
+/// an instruction: `name arg1 arg2 ...`. In the body, `\param` stands for the argument given for
+/// `param`, and `\@` for a suffix unique to each expansion, for labels local to the expansion.
+/// Errors inside an expansion are reported on the line of the invocation, noting the line of the
+/// macro body they came from.
+///
+/// `.include "path"` assembles the contents of another file in place of the directive; see
+/// `parse_file` and `Options` for where included files are looked for.
+///
+/// `.if expression` (true if the expression is not zero), `.ifdef NAME` and `.ifndef NAME`,
+/// followed by lines, an optional `.else` and more lines, and `.endif`, choose which lines to
+/// assemble. Conditions can use the constants defined earlier in the program or in `Options`.
+///
+/// `.allow lint`, `.warn lint` and `.deny lint` set the level of one of the `LINTS` for the rest
+/// of the program; see `assemble_source`.
 /// # Example
 /// A valid program:
 ///
@@ -148,32 +366,162 @@ fn initial_parse_program(program: &str) -> Vec<Result<Option<Instruction>, Strin
 /// use mlem_asm::*;
 /// let invalid_program = "
 ///    noop
-///    move r0 rx;
+///    move r0 r9;
 ///    output invalid;
 ///    ; comment only
 ///
 ///    ";
-///    let expected_errors = Err(vec![(2, "Unknown register name: rx".into()), (3, "Unknown address type specifier: i (expected r, *, or digit).".into())]);
-///    let errors = parse_program(invalid_program);
+///    let expected_errors = vec![
+///        Diagnostic { kind: ErrorKind::UnknownRegister, severity: Severity::Error, file: "<input>".into(), line: 2,
+///                     columns: 11..13, message: "Unknown register name: r9".into(), suggestion: None },
+///        Diagnostic { kind: ErrorKind::UndefinedSymbol, severity: Severity::Error, file: "<input>".into(), line: 3,
+///                     columns: 10..17, message: "Undefined symbol: invalid".into(), suggestion: None },
+///    ];
+///    let errors = parse_program(invalid_program).unwrap_err();
 ///    assert!(errors == expected_errors, "Program resulted in: {:?} not: {:?}", errors, expected_errors);
+///    assert_eq!(errors[0].to_string(), "<input>:3:12: Unknown register name: r9");
 /// ```
-pub fn parse_program(program: &str) -> Result<Program, Vec<(u64, String)>> {
+pub fn parse_program(program: &str) -> Result<Program, Vec<Diagnostic>> {
+    parse_source(program, INPUT_NAME, &Options::default())
+}
+
+/// Parse an entire program, as `parse_program` does, with the given options. `file` is the name
+/// of the program, used in errors; since the program doesn't come from a file, any files it
+/// includes are looked for relative to the current directory.
+///
+/// Errors are in the file they occurred in, which is `file` unless they are in an included file.
+pub fn parse_source(program: &str, file: &str, options: &Options) -> Result<Program, Vec<Diagnostic>> {
+    assemble_source(program, file, options).map(|a| a.program).map_err(only_errors)
+}
+
+/// Read and parse the program in the given file, as `parse_program` does, with the given options.
+/// Files it includes are looked for relative to the file's directory, then in the include paths.
+///
+/// Errors are in the file they occurred in, which is `path` unless they are in an included file.
+pub fn parse_file<P: AsRef<Path>>(path: P, options: &Options) -> Result<Program, Vec<Diagnostic>> {
+    assemble_file(path, options).map(|a| a.program).map_err(only_errors)
+}
+
+/// An assembled program, along with the warnings about it: problems that don't stop it from
+/// being assembled, but might stop it from running as intended.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Assembly {
+    pub program: Program,
+    pub warnings: Vec<Diagnostic>,
+    /// The lines of the program, once macros are expanded and files included, each with the
+    /// instructions it assembled to.
+    pub lines: Vec<AssembledLine>,
+    /// Every label and constant the program defined.
+    pub symbols: Symbols,
+}
+
+/// A line of a program after preprocessing, and the instructions it assembled to.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssembledLine {
+    pub text: String,
+    /// The file the line is in.
+    pub file: String,
+    /// The line of the file. For lines that came from a macro, this is the line the macro was invoked on.
+    pub line: u64,
+    /// Whether the line came from a macro expansion.
+    pub expanded: bool,
+    /// The columns of the line's statement in the source, if it has one. For lines that came from
+    /// a macro, these are the columns of the invocation.
+    pub columns: Range<usize>,
+    /// The indices of the instructions in the program that the line assembled to.
+    pub instructions: Range<usize>,
+}
+
+/// Assemble a program as `parse_source` does, keeping the warnings about it.
+///
+/// Once the program is assembled, the lints in `LINTS` are run over it, at the levels set in
+/// `options` and by directives in the program. By default, a jump to a literal target outside the
+/// program is an error, and everything else the lints find (like a jump to a target in a register
+/// or memory, which can't be checked until the program runs) is a warning.
+///
+/// ```
+/// use mlem_asm::*;
+/// let assembly = assemble_source("move 0 r0\njump r0", "<input>", &Options::default()).unwrap();
+/// assert_eq!(assembly.warnings[0].kind, ErrorKind::ComputedJump);
+/// assert_eq!(assembly.warnings[0].severity, Severity::Warning);
+///
+/// let errors = assemble_source("jump 500", "<input>", &Options::default()).unwrap_err();
+/// assert_eq!(errors[0].message, "Jump target 500 is outside the program, which has 1 instructions.");
+/// ```
+///
+/// If there are any errors, they are returned along with the warnings, in the order they occur.
+pub fn assemble_source(program: &str, file: &str, options: &Options) -> Result<Assembly, Vec<Diagnostic>> {
+    assemble(program, file, None, options)
+}
+
+/// Read and assemble the program in the given file as `parse_file` does, keeping the warnings
+/// about it, as `assemble_source` does.
+pub fn assemble_file<P: AsRef<Path>>(path: P, options: &Options) -> Result<Assembly, Vec<Diagnostic>> {
+    let path = path.as_ref();
+    let name = path.display().to_string();
+    match fs::read_to_string(path) {
+        Ok(program) => assemble(&program, &name, Some(path), options),
+        Err(e) => Err(vec![Diagnostic::new(ErrorKind::Io, format!("Could not read file: {}", e)).at(name, 0)]),
+    }
+}
+
+/// Leave only the errors among some diagnostics.
+fn only_errors(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics.into_iter().filter(|d| d.severity == Severity::Error).collect()
+}
+
+/// Preprocess and assemble a program, read from `path` if it came from a file.
+fn assemble(program: &str, file: &str, path: Option<&Path>, options: &Options) -> Result<Assembly, Vec<Diagnostic>> {
     let mut p = Vec::new();
-    let mut errors = Vec::new();
-    for (n, line) in initial_parse_program(program).into_iter().enumerate() {
-        match line {
+    let mut origins = Vec::new();
+    let mut assembled = Vec::new();
+    let (source_lines, mut errors) = Preprocessor::new(options).run(program, file, path);
+    let mut symbols = Symbols::new();
+    for (name, value) in &options.defines {
+        let location = Location { file: DEFINES_NAME.into(), line: 0, position: 0 };
+        let defined = label::check_symbol_name(name)
+            .and_then(|_| label::check_not_expansion_name(name))
+            .and_then(|_| symbols.define(name, Symbol { kind: SymbolKind::Constant, value: *value, location }));
+        if let Err(e) = defined {
+            errors.push((0, e.at(DEFINES_NAME, 0)));
+        }
+    }
+    // The first pass defines every symbol, so the second can resolve references to labels
+    // that come later in the program.
+    let lines = initial_parse_program(&source_lines, &mut symbols);
+    for (position, (source, line)) in source_lines.iter().zip(lines).enumerate() {
+        let at = source.location(position as u64);
+        assembled.push(AssembledLine {
+            text: source.text.clone(), file: source.file.clone(), line: source.line,
+            expanded: !source.expansions.is_empty(), columns: 0..0, instructions: p.len()..p.len(),
+        });
+        let pieces = match line {
+            Ok(pieces) => pieces,
+            Err(e) => { errors.push((position as u64, source.locate(e))); continue; }
+        };
+        match parse_statement(&source.text, &pieces, &symbols, &at) {
             Ok(v) => {
-                if let Some(i) = v { p.push(i) };
+                let origin = Origin::new(position, &source.text, &pieces);
+                assembled[position].columns = source.invocation.clone().unwrap_or_else(|| origin.statement.clone());
+                origins.extend(v.iter().map(|_| origin.clone()));
+                p.extend(v);
+                assembled[position].instructions.end = p.len();
             },
             Err(e) => {
-                errors.push((n as u64, format!("{}", e)));
+                errors.push((position as u64, source.locate(e)));
             }
         };
     }
-    if errors.len() == 0 {
+    // Lints run over the whole program, since (for instance) jumps can only be checked once its
+    // length is known.
+    errors.extend(lint::report(lint_program(&p, &origins, &source_lines, &symbols), &source_lines, &options.lints));
+    // Keep errors from preprocessing and assembly in program order.
+    errors.sort_by_key(|e| e.0);
+    let diagnostics: Vec<_> = errors.into_iter().map(|(_, e)| e).collect();
+    if diagnostics.iter().all(|e| e.severity == Severity::Warning) {
         // No errors!
-        Ok(p)
+        Ok(Assembly { program: p, warnings: diagnostics, lines: assembled, symbols })
     } else {
-        Err(errors)
+        Err(diagnostics)
     }
 }
@@ -0,0 +1,558 @@
+use std::collections::HashMap;
+use std::fs;
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use super::lex;
+use super::diagnostic::{Diagnostic, ErrorKind};
+use super::label::{split_labels, is_identifier, check_not_expansion_name};
+use super::instruction::parse_instruction;
+use super::pseudo::is_pseudo_instruction;
+use super::address::parse_string_literal;
+use super::symbol::{Location, Symbol, SymbolKind, Symbols};
+use super::expr::evaluate;
+use super::{Options, DEFINES_NAME};
+
+/// How deeply macro invocations may nest inside one another before the assembler gives up,
+/// assuming the macro is (directly or indirectly) recursive.
+pub const MAX_EXPANSION_DEPTH: usize = 32;
+
+/// How many macro expansions a whole program may contain, so that a macro that invokes itself
+/// more than once can't take exponential time to fail.
+pub const MAX_EXPANSIONS: u64 = 10_000;
+
+/// An error found while assembling a program, with the position in the whole program it occurred at.
+pub type PositionedError = (u64, Diagnostic);
+
+/// One level of macro expansion that a line came from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expansion {
+    /// The name of the macro that was expanded.
+    pub name: String,
+    /// The file the macro was defined in.
+    pub file: String,
+    /// The line in that file the macro body line was defined on.
+    pub line: u64,
+}
+
+/// A line of the program after preprocessing, ready to be assembled.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SourceLine {
+    pub text: String,
+    /// The file this line came from.
+    pub file: String,
+    /// The line of the file this came from. For lines that came from a macro, this is the
+    /// line the macro was invoked on.
+    pub line: u64,
+    /// The macro expansions this line came from, outermost first.
+    pub expansions: Vec<Expansion>,
+    /// For lines that came from a macro, the columns of the invocation on the line it was invoked on.
+    pub invocation: Option<Range<usize>>,
+}
+
+impl SourceLine {
+    /// The location of this line, given its position in the whole program.
+    pub fn location(&self, position: u64) -> Location {
+        Location { file: self.file.clone(), line: self.line, position }
+    }
+
+    /// The columns of the source that a token of this line covers. For lines that came from a
+    /// macro, this is the whole invocation, since the token itself isn't in the source.
+    pub fn columns(&self, token: &str) -> Range<usize> {
+        match self.invocation {
+            Some(ref columns) => columns.clone(),
+            None => lex::offset(&self.text, token)..lex::offset(&self.text, token) + token.len(),
+        }
+    }
+
+    /// Place a diagnostic about this line (with columns relative to its text) in the source,
+    /// adding a note about the macro expansions (if any) this line came from.
+    /// Repeated expansions of the same line (as from a recursive macro) are only noted once.
+    pub fn locate(&self, diagnostic: Diagnostic) -> Diagnostic {
+        let mut diagnostic = diagnostic.at(self.file.clone(), self.line);
+        if let Some(ref columns) = self.invocation {
+            diagnostic.columns = columns.clone();
+        }
+        let mut message = diagnostic.message;
+        let mut expansions = self.expansions.iter().rev().peekable();
+        while let Some(expansion) = expansions.next() {
+            let mut times = 1;
+            while expansions.peek() == Some(&expansion) {
+                expansions.next();
+                times += 1;
+            }
+            message = format!("{} (in expansion of macro {} at line {}", message, expansion.name, expansion.line + 1);
+            if expansion.file != self.file {
+                message = format!("{} of {}", message, expansion.file);
+            }
+            message = if times > 1 { format!("{}, {} times)", message, times) } else { format!("{})", message) };
+        }
+        diagnostic.message = message;
+        diagnostic
+    }
+}
+
+/// A macro definition: `.macro name param1 param2 ...` followed by body lines and `.endm`.
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    /// The file the macro is defined in.
+    file: String,
+    /// The lines of the body, with the line of the file each one is on.
+    body: Vec<(u64, String)>,
+}
+
+/// An `.if`, `.ifdef`, or `.ifndef` block in the middle of being preprocessed.
+#[derive(Debug)]
+struct Conditional {
+    /// Whether the lines of the current branch are assembled.
+    active: bool,
+    /// Whether any branch can be assembled at all; false inside a skipped branch of an enclosing block.
+    reachable: bool,
+    /// Whether the condition was true.
+    condition: bool,
+    seen_else: bool,
+    /// Where the block starts, for reporting unterminated blocks.
+    file: String,
+    line: u64,
+    columns: Range<usize>,
+}
+
+/// Expands macros and includes in a program before it is assembled.
+///
+/// Inside a macro body, `\param` is replaced by the argument given for `param`, and `\@` is
+/// replaced by a suffix unique to each expansion, so `loop\@:` defines a label local to the expansion.
+/// Nothing inside quotes is replaced. The suffix is `__` and a number, so names written in the
+/// source can't end that way.
+///
+/// `.include "path"` is replaced by the contents of the file at `path`, which is looked for
+/// relative to the directory of the including file, then in each of the include paths in the options.
+///
+/// `.if expression`, `.ifdef NAME` and `.ifndef NAME`, with an optional `.else` and a closing
+/// `.endif`, choose which lines are assembled. Conditions can use constants defined earlier in the
+/// program and those defined in the options, but not labels.
+#[derive(Debug)]
+pub struct Preprocessor<'o> {
+    options: &'o Options,
+    macros: HashMap<String, Macro>,
+    /// The macro being defined, if any, with its name and the line `.macro` is on.
+    defining: Option<(String, u64, Macro)>,
+    /// The files being included, outermost first, as canonical paths and names; used to find cycles.
+    include_stack: Vec<(PathBuf, String)>,
+    /// The conditional blocks being preprocessed, outermost first.
+    conditionals: Vec<Conditional>,
+    /// How many of `conditionals` were opened outside the file or macro expansion being
+    /// preprocessed, which it can't continue or close.
+    base: usize,
+    /// The constants defined so far, for use in conditions.
+    constants: Symbols,
+    expansion_count: u64,
+    lines: Vec<SourceLine>,
+    /// Errors, each with the position in `lines` it occurred at, its file, line, and message.
+    errors: Vec<PositionedError>,
+}
+
+impl<'o> Preprocessor<'o> {
+    pub fn new(options: &'o Options) -> Self {
+        let mut constants = Symbols::new();
+        for (name, value) in &options.defines {
+            // Bad definitions are reported when the program is assembled.
+            let location = Location { file: DEFINES_NAME.into(), line: 0, position: 0 };
+            let _ = constants.define(name, Symbol { kind: SymbolKind::Constant, value: *value, location });
+        }
+        Preprocessor {
+            options,
+            macros: HashMap::new(),
+            defining: None,
+            include_stack: Vec::new(),
+            conditionals: Vec::new(),
+            base: 0,
+            constants,
+            expansion_count: 0,
+            lines: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Preprocess an entire program, named `file`, returning the lines to assemble and any errors found.
+    /// If the program was read from `path`, includes are looked for relative to its directory;
+    /// otherwise they are looked for relative to the current directory.
+    pub fn run(mut self, program: &str, file: &str, path: Option<&Path>) -> (Vec<SourceLine>, Vec<PositionedError>) {
+        self.process_file(program, file, path);
+        (self.lines, self.errors)
+    }
+
+    fn error(&mut self, diagnostic: Diagnostic) {
+        let position = self.lines.len() as u64;
+        self.errors.push((position, diagnostic));
+    }
+
+    /// Handle every line of a file.
+    fn process_file(&mut self, program: &str, file: &str, path: Option<&Path>) {
+        if let Some(canonical) = path.and_then(|p| fs::canonicalize(p).ok()) {
+            self.include_stack.push((canonical, file.to_string()));
+        }
+        let dir = path.and_then(|p| p.parent()).map(|p| p.to_path_buf());
+        let base = mem::replace(&mut self.base, self.conditionals.len());
+        for (n, line) in program.lines().enumerate() {
+            self.process_line(line, file, n as u64, dir.as_deref());
+        }
+        // Macro definitions and conditional blocks can't span files.
+        if let Some((name, n, _)) = self.defining.take() {
+            self.error(Diagnostic::new(ErrorKind::Macro, format!("Macro {} is missing its .endm", name)).at(file, n));
+        }
+        self.close_conditionals(base);
+        if path.is_some() {
+            self.include_stack.pop();
+        }
+    }
+
+    /// Handle a single line of a file, which may be part of a macro definition.
+    fn process_line(&mut self, text: &str, file: &str, n: u64, dir: Option<&Path>) {
+        let pieces = lex::lex_line(text);
+        let directive = pieces.first().map(|p| p.to_lowercase());
+        if let Some((name, start, mut definition)) = self.defining.take() {
+            match directive.as_deref() {
+                Some(".endm") => { self.macros.insert(name, definition); }
+                Some(".macro") => {
+                    self.error(Diagnostic::new(ErrorKind::Macro, format!("Cannot define a macro inside the definition of macro {}", name))
+                               .spanning(text, &pieces).at(file, n));
+                    self.defining = Some((name, start, definition));
+                }
+                _ => {
+                    if let Err(e) = check_body_names(text, &pieces) {
+                        self.error(e.at(file, n));
+                    }
+                    definition.body.push((n, text.to_string()));
+                    self.defining = Some((name, start, definition));
+                }
+            }
+            return;
+        }
+
+        match directive.as_deref() {
+            Some(".macro") if !self.skipping() => match self.start_definition(text, &pieces, file) {
+                Ok((name, definition)) => { self.defining = Some((name, n, definition)); }
+                Err(e) => { self.error(e.at(file, n)); }
+            },
+            Some(".endm") if !self.skipping() => {
+                self.error(Diagnostic::new(ErrorKind::Macro, ".endm without a matching .macro").within(text, pieces[0]).at(file, n));
+            }
+            _ => {
+                let line = SourceLine { text: text.to_string(), file: file.to_string(), line: n, expansions: Vec::new(), invocation: None };
+                self.emit(line, dir)
+            }
+        }
+    }
+
+    /// Whether lines are being skipped because of a false condition.
+    fn skipping(&self) -> bool {
+        self.conditionals.iter().any(|c| !c.active)
+    }
+
+    /// Handle conditional assembly for a line, returning true if the line has been dealt with:
+    /// either it is a conditional directive, or it is being skipped.
+    fn conditional(&mut self, pieces: &[&str], line: &SourceLine) -> bool {
+        let directive = match pieces.first() {
+            Some(d) => d.to_lowercase(),
+            None => return self.skipping(),
+        };
+        match directive.as_str() {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let reachable = !self.skipping();
+                let condition = reachable && match self.condition(&line.text, pieces) {
+                    Ok(c) => c,
+                    Err(e) => { self.error(line.locate(e)); false }
+                };
+                self.conditionals.push(Conditional {
+                    active: condition, reachable, condition, seen_else: false,
+                    file: line.file.clone(), line: line.line, columns: line.columns(pieces[0]),
+                });
+            }
+            ".else" => {
+                let open = self.conditionals.len() > self.base;
+                let error = match self.conditionals.last_mut().filter(|_| open) {
+                    None => Some(".else without a matching .if".to_string()),
+                    Some(ref c) if c.seen_else => Some(format!("Duplicate .else for the .if on line {}", c.line + 1)),
+                    Some(c) => {
+                        c.seen_else = true;
+                        c.active = c.reachable && !c.condition;
+                        None
+                    }
+                };
+                if let Some(e) = error {
+                    self.error(line.locate(Diagnostic::new(ErrorKind::Conditional, e).within(&line.text, pieces[0])));
+                }
+            }
+            ".endif" => {
+                if self.conditionals.len() > self.base {
+                    self.conditionals.pop();
+                } else {
+                    let e = Diagnostic::new(ErrorKind::Conditional, ".endif without a matching .if").within(&line.text, pieces[0]);
+                    self.error(line.locate(e));
+                }
+            }
+            _ => return self.skipping(),
+        }
+        true
+    }
+
+    /// Decide whether the condition of an `.if`, `.ifdef` or `.ifndef` is true, given the pieces of
+    /// its line, `text`.
+    fn condition(&self, text: &str, pieces: &[&str]) -> Result<bool, Diagnostic> {
+        let directive = pieces[0].to_lowercase();
+        let args = &pieces[1..];
+        if args.is_empty() {
+            return Err(Diagnostic::new(ErrorKind::Conditional, format!("{} needs a condition.", directive)).within(text, pieces[0]));
+        }
+        let at = Location { file: String::new(), line: 0, position: self.lines.len() as u64 };
+        match directive.as_str() {
+            ".if" => {
+                // The condition is everything after the directive, spaces and all.
+                let start = lex::offset(text, args[0]);
+                let end = lex::offset(text, args[args.len() - 1]) + args[args.len() - 1].len();
+                Ok(evaluate(&text[start..end], &self.constants, &at).map_err(|e| e.covering(&text[start..end]).shifted(start))? != 0)
+            }
+            _ if args.len() != 1 => Err(Diagnostic::new(ErrorKind::WrongArgumentCount,
+                                                        format!("Wrong number of arguments to {}. Expected a name; got {}.", directive, args.len()))
+                                        .spanning(text, pieces)),
+            ".ifdef" => Ok(self.constants.get(args[0]).is_some()),
+            _ => Ok(self.constants.get(args[0]).is_none()),
+        }
+    }
+
+    /// Report and discard any conditional blocks opened in the file or macro expansion just
+    /// preprocessed, then go back to the enclosing one's `base`.
+    fn close_conditionals(&mut self, base: usize) {
+        let depth = self.base.min(self.conditionals.len());
+        self.base = base;
+        let unterminated: Vec<Conditional> = self.conditionals.drain(depth..).collect();
+        for c in unterminated {
+            self.error(Diagnostic::new(ErrorKind::Conditional, ".if is missing its .endif").with_columns(c.columns).at(c.file, c.line));
+        }
+    }
+
+    /// Keep track of a constant definition, so it can be used in later conditions.
+    fn track_constant(&mut self, pieces: &[&str], line: &SourceLine) {
+        let at = line.location(self.lines.len() as u64);
+        if let Ok(value) = evaluate(pieces[2], &self.constants, &at) {
+            // Any problems are reported when the program is assembled.
+            let _ = self.constants.define(pieces[1], Symbol { kind: SymbolKind::Constant, value, location: at });
+        }
+    }
+
+    /// Check the first line of a macro definition, `text`, returning the macro's name and an empty body.
+    fn start_definition(&self, text: &str, pieces: &[&str], file: &str) -> Result<(String, Macro), Diagnostic> {
+        let name = match pieces.get(1) {
+            Some(name) => *name,
+            None => return Err(Diagnostic::new(ErrorKind::WrongArgumentCount, ".macro needs a name.").within(text, pieces[0])),
+        };
+        if !is_identifier(name) {
+            return Err(Diagnostic::new(ErrorKind::InvalidName, format!("Invalid macro name: {}", name)).within(text, name));
+        }
+        if parse_instruction(name).is_ok() || is_pseudo_instruction(&[name]) {
+            return Err(Diagnostic::new(ErrorKind::InvalidName, format!("Invalid macro name: {} is already an instruction", name))
+                       .within(text, name));
+        }
+        if self.macros.contains_key(name) {
+            return Err(Diagnostic::new(ErrorKind::Macro, format!("Duplicate macro: {}", name)).within(text, name));
+        }
+        let mut params: Vec<String> = Vec::new();
+        for param in &pieces[2..] {
+            if !is_identifier(param) {
+                return Err(Diagnostic::new(ErrorKind::InvalidName, format!("Invalid macro parameter name: {}", param)).within(text, param));
+            }
+            if params.iter().any(|p| p == param) {
+                return Err(Diagnostic::new(ErrorKind::Macro, format!("Duplicate macro parameter: {}", param)).within(text, param));
+            }
+            params.push(param.to_string());
+        }
+        Ok((name.to_string(), Macro { params, file: file.to_string(), body: Vec::new() }))
+    }
+
+    /// Add a line to the output, expanding it if it invokes a macro or includes a file.
+    fn emit(&mut self, line: SourceLine, dir: Option<&Path>) {
+        let pieces = lex::lex_line(&line.text);
+        if self.conditional(&pieces, &line) {
+            return;
+        }
+        // Malformed labels are left for the assembler to report.
+        let (labels, rest) = match split_labels(&line.text, &pieces) {
+            Ok(v) => v,
+            Err(_) => { self.lines.push(line.clone()); return; }
+        };
+        let directive = rest.first().map(|d| d.to_lowercase());
+        if rest.len() == 3 && (directive.as_deref() == Some(".equ") || directive.as_deref() == Some(".define")) {
+            self.track_constant(&rest, &line);
+        }
+        let is_include = directive.as_deref() == Some(".include");
+        let definition = match rest.first().and_then(|name| self.macros.get(*name)) {
+            Some(definition) => Some(definition.clone()),
+            None if is_include => None,
+            None => { self.lines.push(line.clone()); return; }
+        };
+
+        // Any labels on the line mark the start of what it expands to.
+        if !labels.is_empty() {
+            // Keep the labels where they were, so that errors about them point at the right columns.
+            let end = lex::offset(&line.text, rest[0]);
+            self.lines.push(SourceLine { text: line.text[..end].to_string(), ..line.clone() });
+        }
+
+        match definition {
+            Some(definition) => self.expand(&definition, &rest, &line, dir),
+            None => {
+                if let Err(e) = self.include(&line.text, &rest, dir) {
+                    self.error(line.locate(e));
+                }
+            }
+        }
+    }
+
+    /// Expand an invocation of a macro, given the pieces of the invoking line (without labels).
+    fn expand(&mut self, definition: &Macro, pieces: &[&str], line: &SourceLine, dir: Option<&Path>) {
+        let name = pieces[0];
+        if line.expansions.len() >= MAX_EXPANSION_DEPTH {
+            let message = format!("Macro expansion nested more than {} deep; is {} recursive?", MAX_EXPANSION_DEPTH, name);
+            self.error(line.locate(Diagnostic::new(ErrorKind::Macro, message).within(&line.text, name)));
+            return;
+        }
+        if self.expansion_count >= MAX_EXPANSIONS {
+            // Only report this once; everything after it would say the same.
+            if self.expansion_count == MAX_EXPANSIONS {
+                let message = format!("More than {} macro expansions in the program; is {} recursive?", MAX_EXPANSIONS, name);
+                self.error(line.locate(Diagnostic::new(ErrorKind::Macro, message).within(&line.text, name)));
+                self.expansion_count += 1;
+            }
+            return;
+        }
+        let args = &pieces[1..];
+        if args.len() != definition.params.len() {
+            let message = format!("Wrong number of arguments to macro {}. Expected {}; got {}.",
+                                  name, definition.params.len(), args.len());
+            self.error(line.locate(Diagnostic::new(ErrorKind::WrongArgumentCount, message).spanning(&line.text, pieces)));
+            return;
+        }
+
+        self.expansion_count += 1;
+        let unique = format!("__{}", self.expansion_count);
+        let base = mem::replace(&mut self.base, self.conditionals.len());
+        // Everything in the expansion is in the columns of the outermost invocation.
+        let invocation = match line.invocation {
+            Some(ref columns) => columns.clone(),
+            None => Diagnostic::new(ErrorKind::Macro, "").spanning(&line.text, pieces).columns,
+        };
+        for &(body_line, ref body_text) in &definition.body {
+            let mut expansions = line.expansions.clone();
+            expansions.push(Expansion { name: name.to_string(), file: definition.file.clone(), line: body_line });
+            let inner = SourceLine {
+                text: body_text.clone(), file: line.file.clone(), line: line.line, expansions, invocation: Some(invocation.clone()),
+            };
+            match substitute(body_text, &definition.params, args, &unique) {
+                Ok(expanded) => self.emit(SourceLine { text: expanded, ..inner }, dir),
+                Err(e) => self.error(inner.locate(e)),
+            }
+        }
+        // Conditional blocks can't span macro expansions.
+        self.close_conditionals(base);
+    }
+
+    /// Carry out an `.include "path"` directive, given its line, `text`, and the pieces of it (without labels).
+    fn include(&mut self, text: &str, pieces: &[&str], dir: Option<&Path>) -> Result<(), Diagnostic> {
+        if pieces.len() != 2 {
+            return Err(Diagnostic::new(ErrorKind::WrongArgumentCount,
+                                       format!("Wrong number of arguments to .include. Expected a file name; got {}.", pieces.len() - 1))
+                       .spanning(text, pieces));
+        }
+        let error = |message: String| Diagnostic::new(ErrorKind::Include, message).within(text, pieces[1]);
+        let name: String = parse_string_literal(pieces[1]).map_err(|e| e.within(text, pieces[1]))?.into_iter().collect();
+        let path = self.find_include(&name, dir).map_err(&error)?;
+        let canonical = fs::canonicalize(&path).map_err(|e| error(format!("Could not read included file {}: {}", path.display(), e)))?;
+        if let Some(start) = self.include_stack.iter().position(|f| f.0 == canonical) {
+            let mut cycle: Vec<&str> = self.include_stack[start..].iter().map(|f| f.1.as_str()).collect();
+            let display = path.display().to_string();
+            cycle.push(&display);
+            return Err(error(format!("Include cycle: {}", cycle.join(" -> "))));
+        }
+        let contents = fs::read_to_string(&path).map_err(|e| error(format!("Could not read included file {}: {}", path.display(), e)))?;
+        self.process_file(&contents, &path.display().to_string(), Some(&path));
+        Ok(())
+    }
+
+    /// Find the file an `.include` names: relative to the including file's directory (or the current
+    /// directory, if it isn't known), then relative to each include path.
+    fn find_include(&self, name: &str, dir: Option<&Path>) -> Result<PathBuf, String> {
+        let first = match dir {
+            Some(d) => d.join(name),
+            None => PathBuf::from(name),
+        };
+        let candidates = Some(first).into_iter().chain(self.options.include_paths.iter().map(|p| p.join(name)));
+        for candidate in candidates {
+            if candidate.is_file() {
+                return Ok(candidate);
+            }
+        }
+        Err(format!("Could not find included file {}", name))
+    }
+}
+
+/// Check the names of the labels and constant defined on a line of a macro body, before any
+/// substitution, with `check_not_expansion_name`; once expanded, the names `\@` makes can't be told
+/// apart. Other problems with them are left for the assembler.
+fn check_body_names(text: &str, pieces: &[&str]) -> Result<(), Diagnostic> {
+    let (labels, rest) = match split_labels(text, pieces) {
+        Ok(v) => v,
+        Err(_) => return Ok(()),
+    };
+    let constant = match rest.first().map(|d| d.to_lowercase()).as_deref() {
+        Some(".equ") | Some(".define") => rest.get(1).cloned(),
+        _ => None,
+    };
+    for name in labels.into_iter().chain(constant) {
+        check_not_expansion_name(name).map_err(|e| e.within(text, name))?;
+    }
+    Ok(())
+}
+
+/// Replace `\param` with its argument and `\@` with `unique` in a line of a macro body.
+fn substitute(text: &str, params: &[String], args: &[&str], unique: &str) -> Result<String, Diagnostic> {
+    let mut result = String::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut characters = text.char_indices().peekable();
+    while let Some((i, c)) = characters.next() {
+        if let Some(q) = quote {
+            if escaped { escaped = false; }
+            else if c == '\\' { escaped = true; }
+            else if c == q { quote = None; }
+            result.push(c);
+            continue;
+        }
+        match c {
+            '\'' | '"' => { quote = Some(c); result.push(c); }
+            ';' => { result.push_str(&text[i..]); break; }
+            '\\' => {
+                if let Some(&(_, '@')) = characters.peek() {
+                    characters.next();
+                    result.push_str(unique);
+                    continue;
+                }
+                let start = i + 1;
+                let mut end = start;
+                while let Some(&(j, d)) = characters.peek() {
+                    if !(d.is_ascii_alphanumeric() || d == '_') { break; }
+                    end = j + d.len_utf8();
+                    characters.next();
+                }
+                let name = &text[start..end];
+                match params.iter().position(|p| p == name) {
+                    Some(index) => result.push_str(args[index]),
+                    None => return Err(Diagnostic::new(ErrorKind::Macro, format!("Unknown macro parameter: \\{}", name))
+                                       .with_columns(i..end)),
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,55 @@
+use mlem::{Address, Instruction};
+use super::address::{parse_address, parse_string_literal};
+use super::diagnostic::{Diagnostic, ErrorKind};
+use super::symbol::{Location, Symbols};
+
+/// The names of all the pseudo-instructions.
+pub const PSEUDO_INSTRUCTIONS: [&str; 1] = ["print"];
+
+/// Check whether the pieces of a line make up a pseudo-instruction: a convenience that the
+/// assembler expands into some sequence of real instructions.
+pub fn is_pseudo_instruction(pieces: &[&str]) -> bool {
+    match pieces.first() {
+        Some(name) => PSEUDO_INSTRUCTIONS.contains(&name.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// Work out how many instructions a pseudo-instruction will expand to, so that labels after it
+/// can be placed correctly. If the pseudo-instruction is malformed this guesses; the error is
+/// reported when it is expanded.
+pub fn expanded_length(pieces: &[&str]) -> u64 {
+    match pieces.get(1).map(|s| parse_string_literal(s)) {
+        Some(Ok(characters)) => 2 * characters.len() as u64,
+        _ => 1,
+    }
+}
+
+/// Expand a pseudo-instruction, given its line and the pieces of it, into the real instructions it stands for.
+pub fn expand(line: &str, pieces: &[&str], symbols: &Symbols, at: &Location) -> Result<Vec<Instruction>, Diagnostic> {
+    // The only pseudo-instruction so far is print.
+    expand_print(line, pieces, symbols, at)
+}
+
+/// Expand `print "text" scratch` into a move of each character into the scratch location,
+/// followed by an output of it.
+fn expand_print(line: &str, pieces: &[&str], symbols: &Symbols, at: &Location) -> Result<Vec<Instruction>, Diagnostic> {
+    if pieces.len() != 3 {
+        return Err(Diagnostic::new(ErrorKind::WrongArgumentCount,
+                                   format!("Wrong number of arguments to print. Expected a string and a scratch register; got {}.",
+                                           pieces.len() - 1)).spanning(line, pieces));
+    }
+    let characters = parse_string_literal(pieces[1]).map_err(|e| e.within(line, pieces[1]))?;
+    let scratch = parse_address(pieces[2], symbols, at).map_err(|e| e.within(line, pieces[2]))?;
+    if let Address::Literal(_) = scratch {
+        return Err(Diagnostic::new(ErrorKind::InvalidAddress,
+                                   format!("Cannot use literal {} as the scratch location for print.", pieces[2]))
+            .within(line, pieces[2]));
+    }
+    let mut v = Vec::new();
+    for c in characters {
+        v.push(Instruction::Move(Address::Literal(c as u64), scratch));
+        v.push(Instruction::Output(scratch));
+    }
+    Ok(v)
+}
@@ -0,0 +1,60 @@
+/// Count the edits (insertions, deletions, substitutions, and swaps of neighbouring characters)
+/// needed to turn one string into another, ignoring case.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    // distances[i][j] is the distance between the first i characters of a and the first j of b.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() { row[0] = i; }
+    for (j, d) in distances[0].iter_mut().enumerate() { *d = j; }
+    for i in 1..a.len() + 1 {
+        for j in 1..b.len() + 1 {
+            let substitution = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut d = (distances[i - 1][j] + 1).min(distances[i][j - 1] + 1).min(distances[i - 1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = d;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// Find the candidate closest to a name that wasn't recognized, if any is close enough that it
+/// was probably meant: fewer edits away than half the name's length. Ties go to the earliest candidate.
+pub fn suggest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let mut best: Option<(usize, &'a str)> = None;
+    for &candidate in candidates {
+        let distance = edit_distance(name, candidate);
+        if distance * 2 < name.chars().count() && best.is_none_or(|(d, _)| distance < d) {
+            best = Some((distance, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod test_suggest {
+    use super::*;
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("move", "move"), 0);
+        assert_eq!(edit_distance("MOVE", "move"), 0);
+        assert_eq!(edit_distance("mov", "move"), 1);
+        assert_eq!(edit_distance("rps", "rsp"), 1);
+        assert_eq!(edit_distance("halt", "jump"), 4);
+        assert_eq!(edit_distance("", "noop"), 4);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let registers = ["r0", "r1", "rsp", "rbp"];
+        assert_eq!(suggest("rps", &registers), Some("rsp"));
+        assert_eq!(suggest("RBP1", &registers), Some("rbp"));
+        // Short names are too easily close to something by accident.
+        assert_eq!(suggest("r9", &registers), None);
+        assert_eq!(suggest("jnzz", &["jz", "jnz", "jump"]), Some("jnz"));
+        assert_eq!(suggest("frobnicate", &["jz", "jnz", "jump"]), None);
+    }
+}
@@ -0,0 +1,100 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use super::diagnostic::{Diagnostic, ErrorKind};
+
+/// What a name in a program refers to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolKind {
+    /// A label, marking the index of an instruction. Labels can be used before they are defined.
+    Label,
+    /// A named constant, defined with `.equ` or `.define`. Constants must be defined before use.
+    Constant,
+}
+
+/// A place in a program.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Location {
+    /// The name of the file.
+    pub file: String,
+    /// The line within the file.
+    pub line: u64,
+    /// Where the line falls in the whole program, once includes and macros are expanded.
+    /// This decides whether a constant has been defined yet.
+    pub position: u64,
+}
+
+impl Location {
+    /// Describe the line of this location, as seen from `other`: just the line number if
+    /// they are in the same file, or the line number and file otherwise.
+    /// Line numbers in messages count from one, as `Diagnostic`'s `Display` does.
+    pub fn describe_line(&self, other: &Location) -> String {
+        if self.file == other.file {
+            format!("line {}", self.line + 1)
+        } else {
+            format!("line {} of {}", self.line + 1, self.file)
+        }
+    }
+}
+
+/// A name defined in a program, with its value and where it was defined.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub value: u64,
+    pub location: Location,
+}
+
+/// All of the labels and constants defined in a program.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Symbols {
+    table: HashMap<String, Symbol>,
+    /// The names that have been resolved, so that unused ones can be found.
+    used: RefCell<HashSet<String>>,
+}
+
+impl Symbols {
+    pub fn new() -> Self { Self::default() }
+
+    /// Define a new symbol. Labels and constants share a namespace, and no name can be defined twice.
+    pub fn define(&mut self, name: &str, symbol: Symbol) -> Result<(), Diagnostic> {
+        if let Some(existing) = self.table.get(name) {
+            return Err(Diagnostic::new(ErrorKind::DuplicateSymbol,
+                                       format!("Duplicate symbol: {} (first defined on {})",
+                                               name, existing.location.describe_line(&symbol.location))).covering(name));
+        }
+        self.table.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    /// Look up a symbol by name, regardless of where it was defined.
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.table.get(name)
+    }
+
+    /// Every symbol defined, with its name, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        self.table.iter()
+    }
+
+    /// Check whether the named symbol has been resolved anywhere.
+    pub fn is_used(&self, name: &str) -> bool {
+        self.used.borrow().contains(name)
+    }
+
+    /// Find the value of the named symbol as used at the given location.
+    /// Constants used before the line they are defined on are errors.
+    pub fn resolve(&self, name: &str, at: &Location) -> Result<u64, Diagnostic> {
+        match self.table.get(name) {
+            Some(s) if s.kind == SymbolKind::Constant && s.location.position > at.position => {
+                Err(Diagnostic::new(ErrorKind::UsedBeforeDefinition,
+                                    format!("Constant {} used before its definition on {}", name, s.location.describe_line(at)))
+                    .covering(name))
+            }
+            Some(s) => {
+                self.used.borrow_mut().insert(name.to_string());
+                Ok(s.value)
+            }
+            None => Err(Diagnostic::new(ErrorKind::UndefinedSymbol, format!("Undefined symbol: {}", name)).covering(name)),
+        }
+    }
+}
@@ -1,8 +1,20 @@
-use super::{parse_line, parse_program};
-use super::super::{Instruction, Address, Register};
+use super::{parse_line, parse_program, parse_file, parse_source, assemble_source, Options, Diagnostic, ErrorKind, Severity, Level};
+use super::super::{Instruction, Address, Register, Program};
+use temp_dir::TempDir;
+
+/// The line and message of each error a program failed to assemble with.
+fn errors(result: Result<Program, Vec<Diagnostic>>) -> Vec<(u64, String)> {
+    result.unwrap_err().into_iter().map(|e| (e.line, e.message)).collect()
+}
+
+/// The message of the error a line failed to parse with.
+fn message(result: Result<Option<Instruction>, Diagnostic>) -> String {
+    result.unwrap_err().message
+}
+
 #[test]
 fn test() {
-    assert!(parse_line("").unwrap() == None);
+    assert!(parse_line("").unwrap().is_none());
     assert!(parse_line("noop").unwrap() == Some(Instruction::NoOp));
     assert!(parse_line("noop; some comments").unwrap() == Some(Instruction::NoOp));
 }
@@ -15,9 +27,10 @@ fn test_single_argument() {
 
 #[test]
 fn test_radix_literals() {
-    assert_eq!(parse_line("move 0xff 0b11111111").unwrap(), Some(Instruction::Move(Address::Literal(255), Address::Literal(255))));
+    assert_eq!(parse_line("move 0xff *0b11111111").unwrap(), Some(Instruction::Move(Address::Literal(255), Address::MemAbs(255))));
 }
 
+#[test]
 fn test_memory_absolute() {
     assert_eq!(parse_line("move *0xff R0").unwrap(), Some(Instruction::Move(Address::MemAbs(255), Address::RegAbs(Register::R0))));
 }
@@ -36,14 +49,14 @@ fn test_parse_invalid_program() {
     ; comment only
     noop;
     move R0 R1;
-    move Rx R1;
+    move R9 R1;
     move R0 1024p;
     ";
-    let expected_errors = Err(vec![
-            (4, "Unknown register name: rx".into()),
-            (5, "Could not parse literal: invalid digit found in string".into())
-    ]);
-    let errors = parse_program(invalid_program);
+    let expected_errors = vec![
+            (4, "Unknown register name: r9".to_string()),
+            (5, "Could not parse literal: invalid digit found in string".to_string())
+    ];
+    let errors = errors(parse_program(invalid_program));
     assert!(errors == expected_errors, "Program resulted in: {:?} not: {:?}", errors, expected_errors);
 }
 
@@ -64,3 +77,492 @@ fn test_parse_valid_program() {
     let program = parse_program(valid_program);
     assert!(program == expected_program, "Program resulted in: {:?} not: {:?}", program, expected_program);
 }
+
+#[test]
+fn test_labels() {
+    let program = "
+    start: move 3 R0
+    loop:
+        sub R0 1
+        jnz loop R0
+        jump end
+    end: halt
+    ";
+    assert_eq!(parse_program(program), Ok(vec![
+            Instruction::Move(Address::Literal(3), Address::RegAbs(Register::R0)),
+            Instruction::Sub(Address::RegAbs(Register::R0), Address::Literal(1)),
+            Instruction::JumpNotZero(Address::Literal(1), Address::RegAbs(Register::R0)),
+            Instruction::Jump(Address::Literal(4)),
+            Instruction::Halt,
+    ]));
+}
+
+#[test]
+fn test_label_errors() {
+    let program = "
+    loop: noop
+    loop: noop
+    r0: noop
+    jump nowhere
+    ";
+    assert_eq!(errors(parse_program(program)), vec![
+            (2, "Duplicate symbol: loop (first defined on line 2)".to_string()),
+            (3, "Invalid symbol name: r0 is a register name".to_string()),
+            (4, "Undefined symbol: nowhere".to_string()),
+    ]);
+}
+
+#[test]
+fn test_constants() {
+    let program = "
+    .equ COUNT 96
+    .define BUFFER 0x10
+    .equ ALSO_COUNT COUNT
+    move ALSO_COUNT R7
+    move R0 *BUFFER
+    ";
+    assert_eq!(parse_program(program), Ok(vec![
+            Instruction::Move(Address::Literal(96), Address::RegAbs(Register::R7)),
+            Instruction::Move(Address::RegAbs(Register::R0), Address::MemAbs(16)),
+    ]));
+}
+
+#[test]
+fn test_constant_errors() {
+    let program = "
+    move COUNT R7
+    .equ COUNT 96
+    .equ COUNT 97
+    .equ R1 3
+    .equ
+    ";
+    assert_eq!(errors(parse_program(program)), vec![
+            (1, "Constant COUNT used before its definition on line 3".to_string()),
+            (3, "Duplicate symbol: COUNT (first defined on line 3)".to_string()),
+            (4, "Invalid symbol name: R1 is a register name".to_string()),
+            (5, "Wrong number of arguments to .equ. Expected a name and a value; got 0.".to_string()),
+    ]);
+}
+
+#[test]
+fn test_constant_expressions() {
+    let program = "
+    .equ BASE 0x10
+    .equ END_OF_BUFFER (BASE + 8)
+    start: move (BASE + 4*2) R0
+    move R0 *(END_OF_BUFFER-1)
+    jump end-1
+    end: jz start ~0
+    move (1/0) R0
+    ";
+    assert_eq!(errors(parse_program(program)), vec![(7, "Division by zero: 1 / 0".to_string())]);
+    let program = &program[..program.find("move (1/0)").unwrap()];
+    assert_eq!(parse_program(program), Ok(vec![
+            Instruction::Move(Address::Literal(24), Address::RegAbs(Register::R0)),
+            Instruction::Move(Address::RegAbs(Register::R0), Address::MemAbs(23)),
+            Instruction::Jump(Address::Literal(2)),
+            Instruction::JumpIfZero(Address::Literal(0), Address::Literal(u64::MAX)),
+    ]));
+}
+
+#[test]
+fn test_character_literals() {
+    assert_eq!(parse_line("move 'A' R0").unwrap(), Some(Instruction::Move(Address::Literal(65), Address::RegAbs(Register::R0))));
+    assert_eq!(parse_line("move '\\n' R0").unwrap(), Some(Instruction::Move(Address::Literal(10), Address::RegAbs(Register::R0))));
+    assert_eq!(parse_line("move '\\x7f' R0").unwrap(), Some(Instruction::Move(Address::Literal(127), Address::RegAbs(Register::R0))));
+    assert_eq!(parse_line("move '\\'' R0").unwrap(), Some(Instruction::Move(Address::Literal(39), Address::RegAbs(Register::R0))));
+    assert_eq!(parse_line("move ';' R0 ; comment").unwrap(), Some(Instruction::Move(Address::Literal(59), Address::RegAbs(Register::R0))));
+    assert_eq!(parse_line("move ' ' R0").unwrap(), Some(Instruction::Move(Address::Literal(32), Address::RegAbs(Register::R0))));
+    assert_eq!(message(parse_line("move 'ab' R0")), "Character literal must contain exactly one character: 'ab'");
+    assert_eq!(message(parse_line("move '\\q' R0")), "Unknown escape sequence: \\q");
+    assert_eq!(message(parse_line("move '\\x+1' R0")), "Invalid escape sequence: \\x+1 (expected two hex digits)");
+    assert_eq!(message(parse_line("move '\\x-1' R0")), "Invalid escape sequence: \\x-1 (expected two hex digits)");
+    assert_eq!(message(parse_line("move '\\x7' R0")), "Invalid escape sequence: \\x7 (expected two hex digits)");
+    assert_eq!(message(parse_line("move 'a R0")), "Unterminated character literal: 'a R0");
+
+    // What an unclosed quote or parenthesis takes in isn't parsed; the diagnostic points at where it opens.
+    let error = parse_line("print \"Hello R0 ; comment").unwrap_err();
+    assert_eq!((error.message.as_str(), error.columns), ("Unterminated string literal: \"Hello R0 ; comment", 6..7));
+    let error = parse_line("move *( R0").unwrap_err();
+    assert_eq!((error.kind, error.message.as_str(), error.columns), (ErrorKind::InvalidExpression, "Unclosed parenthesis: ( R0", 6..7));
+}
+
+#[test]
+fn test_print() {
+    let program = "
+    print \"Hi\\n\" R1
+    after: jump after
+    ";
+    let r1 = Address::RegAbs(Register::R1);
+    assert_eq!(parse_program(program), Ok(vec![
+            Instruction::Move(Address::Literal('H' as u64), r1),
+            Instruction::Output(r1),
+            Instruction::Move(Address::Literal('i' as u64), r1),
+            Instruction::Output(r1),
+            Instruction::Move(Address::Literal('\n' as u64), r1),
+            Instruction::Output(r1),
+            Instruction::Jump(Address::Literal(6)),
+    ]));
+    assert_eq!(parse_line("print \"\" R0"), Ok(None));
+    assert_eq!(message(parse_line("print \"ab\" R0")), "print expands to 4 instructions; it can only be used in a program.");
+    assert_eq!(message(parse_line("print \"ab\" 5")), "Cannot use literal 5 as the scratch location for print.");
+}
+
+#[test]
+fn test_macros() {
+    let program = "
+    .macro swap a b
+        push \\a
+        move \\b \\a
+        pop \\b
+    .endm
+    .macro countdown register
+    loop\\@: sub \\register 1 ; \\not a parameter in a comment
+        jnz loop\\@ \\register
+    .endm
+    start: swap R0 R1
+    countdown R2
+    countdown R3
+    ";
+    let (r0, r1, r2, r3) = (Address::RegAbs(Register::R0), Address::RegAbs(Register::R1),
+                            Address::RegAbs(Register::R2), Address::RegAbs(Register::R3));
+    assert_eq!(parse_program(program), Ok(vec![
+            Instruction::Push(r0),
+            Instruction::Move(r1, r0),
+            Instruction::Pop(r1),
+            Instruction::Sub(r2, Address::Literal(1)),
+            Instruction::JumpNotZero(Address::Literal(3), r2),
+            Instruction::Sub(r3, Address::Literal(1)),
+            Instruction::JumpNotZero(Address::Literal(5), r3),
+    ]));
+}
+
+#[test]
+fn test_macro_errors() {
+    let program = "
+    .macro bad a
+        move \\a r9
+    .endm
+    .macro forever
+        forever
+    .endm
+    bad R0
+    bad
+    forever
+    .macro move
+    .endm
+    ";
+    assert_eq!(errors(parse_program(program)), vec![
+            (7, "Unknown register name: r9 (in expansion of macro bad at line 3)".to_string()),
+            (8, "Wrong number of arguments to macro bad. Expected 1; got 0.".to_string()),
+            (9, "Macro expansion nested more than 32 deep; is forever recursive? (in expansion of macro forever at line 6, 32 times)".to_string()),
+            (10, "Invalid macro name: move is already an instruction".to_string()),
+            (11, ".endm without a matching .macro".to_string()),
+    ]);
+
+    // The names `\\@` makes can't be written in the source, so they can't collide with it.
+    let program = "
+    loop__1: noop
+    .macro m
+        loop\\@: jnz loop\\@ R0
+        done__2: halt
+    .endm
+    m
+    .equ LIMIT__3 1
+    loop__x: not__1x: jump loop__1
+    ";
+    let reserved = |name| format!("Invalid symbol name: {} (names ending in __ and a number are kept for macro expansions)", name);
+    assert_eq!(errors(parse_program(program)), vec![(1, reserved("loop__1")), (4, reserved("done__2")), (7, reserved("LIMIT__3"))]);
+    assert_eq!(Options::default().define("A__1=2").unwrap_err().message, reserved("A__1"));
+}
+
+#[test]
+fn test_macro_expansion_limit() {
+    let program = "
+    .macro twice
+        twice
+        twice
+    .endm
+    twice
+    ";
+    let errors = parse_program(program).unwrap_err();
+    assert!(errors.iter().any(|e| e.message.starts_with("More than 10000 macro expansions")), "{:?}", errors);
+}
+
+/// Write the given files into a fresh temporary directory, which is removed when it's dropped.
+fn write_files(test_name: &str, files: &[(&str, &str)]) -> TempDir {
+    let dir = TempDir::new(test_name);
+    for &(name, contents) in files {
+        dir.write(name, contents);
+    }
+    dir
+}
+
+#[test]
+fn test_include() {
+    let dir = write_files("include", &[
+        ("main.asm", ".include \"lib/macros.asm\"\nstart: inc R0\n.include \"tail.asm\"\n"),
+        ("lib/macros.asm", ".include \"constants.asm\"\n.macro inc r\n    add \\r STEP\n.endm\n"),
+        ("lib/constants.asm", ".equ STEP 2\n"),
+        ("search/tail.asm", "jump start\n"),
+    ]);
+    let options = Options { include_paths: vec![dir.path().join("search")], ..Options::default() };
+    assert_eq!(parse_file(dir.path().join("main.asm"), &options), Ok(vec![
+            Instruction::Add(Address::RegAbs(Register::R0), Address::Literal(2)),
+            Instruction::Jump(Address::Literal(0)),
+    ]));
+    let errors = parse_file(dir.path().join("main.asm"), &Options::default()).unwrap_err();
+    assert_eq!(errors, vec![Diagnostic {
+        kind: ErrorKind::Include, severity: Severity::Error, file: dir.path().join("main.asm").display().to_string(), line: 2, columns: 9..19,
+        message: "Could not find included file tail.asm".into(), suggestion: None,
+    }]);
+}
+
+#[test]
+fn test_include_errors() {
+    let dir = write_files("include-errors", &[
+        ("a.asm", "noop\n.include \"b.asm\"\n"),
+        ("b.asm", "move R0 r9\n.include \"a.asm\"\n"),
+    ]);
+    let a = dir.path().join("a.asm").display().to_string();
+    let b = dir.path().join("b.asm").display().to_string();
+    let errors: Vec<_> = parse_file(dir.path().join("a.asm"), &Options::default()).unwrap_err()
+        .into_iter().map(|e| (e.file, e.line, e.message)).collect();
+    assert_eq!(errors, vec![
+            (b.clone(), 0, "Unknown register name: r9".to_string()),
+            (b.clone(), 1, format!("Include cycle: {} -> {} -> {}", a, b, dir.path().join("a.asm").display())),
+    ]);
+
+    // Nor can an included file continue or close a block opened outside it.
+    let dir = write_files("include-conditionals", &[
+        ("main.asm", ".if 1\n.include \"endif.asm\"\n.endif\nhalt\n"),
+        ("endif.asm", ".endif\n"),
+    ]);
+    let errors: Vec<_> = parse_file(dir.path().join("main.asm"), &Options::default()).unwrap_err()
+        .into_iter().map(|e| (e.file, e.line, e.message)).collect();
+    assert_eq!(errors, vec![(dir.path().join("endif.asm").display().to_string(), 0, ".endif without a matching .if".to_string())]);
+}
+
+#[test]
+fn test_conditionals() {
+    let program = "
+    .ifndef LEVEL
+    .equ LEVEL 0
+    .endif
+    .if LEVEL >= 2
+        output R0
+    .else
+        .ifdef DEBUG
+            output R1
+        .else
+            output R2
+            .if 1
+                .macro skipped_in_release
+                .endm
+            .endif
+        .endif
+    .endif
+    .ifdef DEBUG
+        .macro trace
+            output R7
+        .endm
+        trace
+    .endif
+    halt
+    ";
+    let output = |r| Instruction::Output(Address::RegAbs(r));
+    let variant = |defines: &[&str]| {
+        let mut options = Options::default();
+        for d in defines { options.define(d).unwrap(); }
+        parse_source(program, "variant", &options)
+    };
+    assert_eq!(variant(&[]), Ok(vec![output(Register::R2), Instruction::Halt]));
+    assert_eq!(variant(&["DEBUG"]), Ok(vec![output(Register::R1), output(Register::R7), Instruction::Halt]));
+    assert_eq!(variant(&["DEBUG", "LEVEL=2"]), Ok(vec![output(Register::R0), output(Register::R7), Instruction::Halt]));
+}
+
+#[test]
+fn test_conditional_errors() {
+    let program = "
+    .if UNDEFINED
+    .else
+    .else
+    .endif
+    .endif
+    .ifdef
+    .if 1
+    ";
+    assert_eq!(errors(parse_program(program)), vec![
+            (1, "Undefined symbol: UNDEFINED".to_string()),
+            (3, "Duplicate .else for the .if on line 2".to_string()),
+            (5, ".endif without a matching .if".to_string()),
+            (6, ".ifdef needs a condition.".to_string()),
+            (6, ".if is missing its .endif".to_string()),
+            (7, ".if is missing its .endif".to_string()),
+    ]);
+
+    // A macro can't continue or close a block opened outside it.
+    let program = "
+    .macro m
+    .else
+    .endif
+    .endm
+    .if 1
+    m
+    .endif
+    halt
+    ";
+    assert_eq!(errors(parse_program(program)), vec![
+            (6, ".else without a matching .if (in expansion of macro m at line 3)".to_string()),
+            (6, ".endif without a matching .if (in expansion of macro m at line 4)".to_string()),
+    ]);
+}
+
+#[test]
+fn test_diagnostics() {
+    let program = "
+    move R0 r9
+    loop: output (1 + missing)
+    .equ SIZE 'ab'
+    zero R0 R1 R2 ; too many
+    .macro bad
+        move R0 r9
+    .endm
+    noop ; then
+        bad
+    ";
+    let errors: Vec<_> = parse_program(program).unwrap_err().into_iter().map(|e| (e.kind, e.line, e.columns)).collect();
+    assert_eq!(errors, vec![
+            (ErrorKind::UnknownRegister, 1, 12..14),
+            (ErrorKind::UndefinedSymbol, 2, 22..29),
+            (ErrorKind::InvalidLiteral, 3, 14..18),
+            (ErrorKind::WrongArgumentCount, 4, 4..17),
+            (ErrorKind::UnknownRegister, 9, 8..11),
+    ]);
+
+    let error = parse_line("jump 'ab\\q'").unwrap_err();
+    assert_eq!((error.kind, error.columns.clone()), (ErrorKind::InvalidLiteral, 8..10));
+    assert_eq!(error.to_string(), "1:9: Unknown escape sequence: \\q");
+    let error = parse_line("frobnicate R0").unwrap_err();
+    assert_eq!((error.kind, error.columns), (ErrorKind::UnknownInstruction, 0..10));
+    let error = parse_line(".include \"x.asm\"").unwrap_err();
+    assert_eq!((error.kind, error.columns), (ErrorKind::OnlyInProgram, 0..8));
+
+    let mut options = Options::default();
+    let error = options.define("X=1+").unwrap_err();
+    assert_eq!(error.to_string(), "<command line>:1:3: Expected a value, found the end of the expression.");
+}
+
+#[test]
+fn test_render() {
+    let error = parse_line("\tjump (1 + missing) ; far").unwrap_err();
+    assert_eq!(error.render(Some("\tjump (1 + missing) ; far"), false),
+               "error: Undefined symbol: missing\n --> 1:12\n  |\n1 | \tjump (1 + missing) ; far\n  | \t          ^^^^^^^");
+    assert_eq!(error.render(None, false), "error: Undefined symbol: missing\n --> 1:12");
+    let colored = error.render(Some("\tjump (1 + missing) ; far"), true);
+    assert!(colored.starts_with("\x1b[1;31merror\x1b[0m"), "{:?}", colored);
+    assert!(colored.ends_with("\x1b[1;31m^^^^^^^\x1b[0m"), "{:?}", colored);
+    // Columns that aren't in the line can't be underlined, but the line is still shown.
+    assert_eq!(error.render(Some("jump"), false), "error: Undefined symbol: missing\n --> 1:12\n  |\n1 | jump");
+}
+
+#[test]
+fn test_suggestions() {
+    let suggestion = |line| parse_line(line).unwrap_err().suggestion;
+    assert_eq!(suggestion("jnzz 3 R0"), Some("jnz".into()));
+    assert_eq!(suggestion("JUMPIFZRO 3 R0"), Some("jumpifzero".into()));
+    assert_eq!(suggestion("prnt \"a\" R0"), Some("print".into()));
+    assert_eq!(suggestion("move rps R0"), Some("rsp".into()));
+    assert_eq!(suggestion("move R0 *rbq"), Some("*rbp".into()));
+    assert_eq!(suggestion("frobnicate R0"), None);
+    assert_eq!(suggestion("move rx R0"), None);
+
+    // Names that aren't shaped like registers are symbols, but names close to a register suggest it.
+    let error = parse_line("move R0 result").unwrap_err();
+    assert_eq!((error.kind, error.message.as_str()), (ErrorKind::UndefinedSymbol, "Undefined symbol: result"));
+    let error = parse_line("move rps R0").unwrap_err();
+    assert_eq!((error.kind, error.suggestion), (ErrorKind::UndefinedSymbol, Some("rsp".into())));
+    let error = parse_line("move R10 R0").unwrap_err();
+    assert_eq!((error.kind, error.message.as_str()), (ErrorKind::UnknownRegister, "Unknown register name: r10"));
+
+    let error = parse_line("mov R0 R1").unwrap_err();
+    assert_eq!(error.render(Some("mov R0 R1"), false),
+               "error: Unknown instruction: mov\n --> 1:1\n  |\n1 | mov R0 R1\n  | ^^^\n  = help: did you mean `move`?");
+}
+
+#[test]
+fn test_operand_kinds() {
+    assert_eq!(message(parse_line("zero 5")), "Operand 1 of zero is written to, so it can't be the literal 5.");
+    assert_eq!(message(parse_line("POP 0x10")), "Operand 1 of pop is written to, so it can't be the literal 0x10.");
+    assert_eq!(message(parse_line("iput 'a'")), "Operand 1 of iput is written to, so it can't be the literal 'a'.");
+    let error = parse_line("move R0 (3 + 4)").unwrap_err();
+    assert_eq!((error.kind, error.columns), (ErrorKind::InvalidOperand, 8..15));
+    assert_eq!(error.message, "Operand 2 of move is written to, so it can't be the literal (3 + 4).");
+    assert_eq!(message(parse_line("add 1 R0")), "Operand 1 of add is written to, so it can't be the literal 1.");
+    // Memory addresses given as literals are fine, as are literal sources.
+    assert!(parse_line("zero *5").is_ok());
+    assert!(parse_line("move 7 R0").is_ok());
+    assert!(parse_line("sub R0 1").is_ok());
+    assert!(parse_line("jz 4 0").is_ok());
+    assert!(parse_line("push 9").is_ok());
+    // Arity is checked before operand kinds.
+    assert_eq!(message(parse_line("zero 5 6")), "Wrong number of arguments. Got 2.");
+}
+
+#[test]
+fn test_jump_range() {
+    let options = Options::default();
+    let problems = assemble_source("noop\njump 500\njz 2 R0\nhalt", "<input>", &options).unwrap_err();
+    assert_eq!(problems.len(), 1);
+    assert_eq!((problems[0].kind, problems[0].line, problems[0].columns.clone()), (ErrorKind::JumpOutOfRange, 1, 5..8));
+    assert_eq!(problems[0].message, "Jump target 500 is outside the program, which has 4 instructions.");
+    // Labels always mark instructions in the program, except one at the very end.
+    assert!(parse_program("loop: jnz loop R0\nhalt").is_ok());
+    assert_eq!(errors(parse_program("jump end\nend:")), vec![(0, "Jump target 1 is outside the program, which has 1 instructions.".into())]);
+    // Targets computed at run time are only warned about.
+    let assembly = assemble_source("jump R0\njnz *R1 R0\nhalt", "<input>", &options).unwrap();
+    let warnings: Vec<_> = assembly.warnings.iter().map(|w| (w.severity, w.line, w.columns.clone())).collect();
+    assert_eq!(warnings, vec![(Severity::Warning, 0, 5..7), (Severity::Warning, 1, 4..7)]);
+    assert_eq!(assembly.warnings[1].message, "Jump target *R1 is computed at run time, so it can't be checked against the length of the program.");
+    assert!(parse_program("jump R0").is_ok());
+    // Warnings still come with errors, in order.
+    let diagnostics = assemble_source("jump R0\njump 9\nhalt", "<input>", &options).unwrap_err();
+    assert_eq!(diagnostics.iter().map(|d| d.kind).collect::<Vec<_>>(), vec![ErrorKind::ComputedJump, ErrorKind::JumpOutOfRange]);
+    assert_eq!(errors(parse_program("jump R0\njump 9")).len(), 1);
+}
+
+/// The kind, severity and line of each warning a program assembled with.
+fn warnings(program: &str, options: &Options) -> Vec<(ErrorKind, u64)> {
+    assemble_source(program, "<input>", options).unwrap().warnings.into_iter().map(|w| (w.kind, w.line)).collect()
+}
+
+#[test]
+fn test_lints() {
+    let options = Options::default();
+    let program = "start: move R0 R0\nsub R1 0\nunused: jz start R1\nhalt\noutput R0\nnoop\n";
+    assert_eq!(warnings(program, &options), vec![
+        (ErrorKind::SelfMove, 0), (ErrorKind::SubZero, 1), (ErrorKind::UnusedLabel, 2), (ErrorKind::Unreachable, 4),
+    ]);
+    let assembly = assemble_source(program, "<input>", &options).unwrap();
+    assert_eq!(assembly.warnings[0].message, "Moving R0 to itself does nothing.");
+    assert_eq!((assembly.warnings[1].columns.clone(), assembly.warnings[2].columns.clone()), (7..8, 0..6));
+    assert_eq!(assembly.warnings[3].message, "Unreachable instruction: it follows a halt, and no label or jump leads to it.");
+    assert_eq!(assembly.warnings[3].render(Some("output R0"), false).lines().next(),
+               Some("warning[unreachable]: Unreachable instruction: it follows a halt, and no label or jump leads to it."));
+    // Labels and literal jumps lead to instructions after a halt or jump.
+    assert_eq!(warnings("jump 3\nhalt\nend: jump 1\nhalt\n", &options), vec![(ErrorKind::UnusedLabel, 2)]);
+    assert_eq!(warnings("noop\n", &options), vec![(ErrorKind::NoHalt, 0)]);
+
+    // Lints can be set in options, and overridden by directives for the rest of the program.
+    let mut options = Options::default();
+    options.set_lint("self-move", Level::Deny).unwrap();
+    options.set_lint("no-halt", Level::Allow).unwrap();
+    assert_eq!(errors(parse_source("move R1 R1\nnoop", "<input>", &options)), vec![(0, "Moving R1 to itself does nothing.".into())]);
+    assert_eq!(warnings(".warn self-move\nmove R1 R1\n.allow self-move\nmove R2 R2", &options), vec![(ErrorKind::SelfMove, 1)]);
+    assert_eq!(warnings(".allow jump-out-of-range\njz 7 R0\nhalt", &options), vec![]);
+    assert_eq!(errors(parse_program(".deny computed-jump\njump R0\nhalt")).len(), 1);
+
+    let error = parse_line(".allow unused-lable").unwrap_err();
+    assert_eq!((error.kind, error.columns, error.suggestion), (ErrorKind::UnknownLint, 7..19, Some("unused-label".into())));
+    assert_eq!(message(parse_line(".deny")), "Wrong number of arguments to .deny. Expected the name of a lint; got 0.");
+}
@@ -0,0 +1,157 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::io::{Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+use mlem::{Address, Machine, Outcome, Program, Register};
+
+/// How many instructions are run between checks of the time limit.
+const CHUNK: u64 = 10_000;
+
+/// The machine to run a program on, and how long to let it run.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RunOptions {
+    /// The number of words of memory the machine has. This must be at least 1.
+    pub memory: usize,
+    /// The most instructions to execute before stopping the program, if there is a limit.
+    pub max_cycles: Option<u64>,
+    /// The longest to let the program run before stopping it, if there is a limit. This is checked
+    /// between instructions, so it doesn't stop a program that is waiting for input.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions { memory: 65535, max_cycles: None, timeout: None }
+    }
+}
+
+/// How running a program ended.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RunOutcome {
+    /// The program halted successfully.
+    Halt,
+    /// The program executed an `illegal` instruction.
+    Illegal,
+    /// The program went outside the machine: it wrote past the end of memory, overran the stack,
+    /// or jumped or ran past the end of the program.
+    OutOfBounds(String),
+    /// The program broke the machine some other way, like writing to a literal or failing to read input.
+    Fault(String),
+    /// The program executed the most instructions allowed without stopping.
+    CycleLimit,
+    /// The program ran for the longest time allowed without stopping.
+    Timeout,
+}
+
+/// Run a program on a new machine, with the given input and output, until it halts or faults or
+/// reaches one of the limits in `options`. Gives how it ended, and the number of instructions it
+/// executed.
+///
+/// ```
+/// use mlem_asm::*;
+/// let program = parse_program("loop: jump loop").unwrap();
+/// let options = RunOptions { max_cycles: Some(100), ..RunOptions::default() };
+/// let (outcome, cycles) = run_program(program, &mut std::io::empty(), &mut std::io::sink(), &options);
+/// assert_eq!((outcome, cycles), (RunOutcome::CycleLimit, 100));
+/// ```
+pub fn run_program(program: Program, input: &mut dyn Read, output: &mut dyn Write, options: &RunOptions) -> (RunOutcome, u64) {
+    if options.memory == 0 {
+        return (RunOutcome::Fault("The machine must have at least one word of memory.".into()), 0);
+    }
+    // The machine would start by executing an instruction that isn't there.
+    if program.is_empty() {
+        return (fault("IP beyond program length. IP = 0, length = 0".into()), 0);
+    }
+    // The machine only grows its memory when writing past the end of it, and can fail to when
+    // writing just at the end; starting with all of it (and the word past the end, which it
+    // allows writing to) means writes in range always succeed.
+    let mut memory = Vec::new();
+    match options.memory.checked_add(1) {
+        Some(words) if memory.try_reserve_exact(words).is_ok() => memory.resize(words, 0),
+        _ => return (RunOutcome::Fault(format!("Could not allocate {} words of memory.", options.memory)), 0),
+    }
+    let mut m = Machine::new(options.memory, input, output);
+    m.load_memory(memory);
+    m.load_program(program);
+    let start = Instant::now();
+    let mut executed = 0;
+    loop {
+        if options.timeout.is_some_and(|t| start.elapsed() >= t) {
+            return (RunOutcome::Timeout, executed);
+        }
+        let chunk = match options.max_cycles {
+            Some(max) if executed >= max => return (RunOutcome::CycleLimit, executed),
+            Some(max) => CHUNK.min(max - executed),
+            None => CHUNK,
+        };
+        // The instruction that halts or faults isn't counted.
+        match run_for(&mut m, chunk) {
+            (Ok(Outcome::Continue), cycles) => executed += cycles,
+            (Ok(Outcome::Halt), cycles) => return (RunOutcome::Halt, executed + cycles + 1),
+            (Ok(Outcome::Fault(f)), cycles) => return (fault(f), executed + cycles + 1),
+            (Err(message), cycles) => return (panicked(&m, message), executed + cycles + 1),
+        }
+    }
+}
+
+thread_local! {
+    /// Whether this thread is running a machine, whose panics are reported as faults rather than printed.
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Execute at most the given number of instructions, as `Machine::run_for` does, but giving the
+/// message of a panic in the machine (which it has for some ways a stack can overrun) as an error.
+fn run_for(m: &mut Machine, cycles: u64) -> (Result<Outcome, String>, u64) {
+    static QUIET: Once = Once::new();
+    QUIET.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| if !RUNNING.with(Cell::get) { previous(info) }));
+    });
+    let mut executed = 0;
+    RUNNING.with(|r| r.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        while executed < cycles {
+            match m.execute_next() {
+                Outcome::Continue => executed += 1,
+                other => return other,
+            }
+        }
+        Outcome::Continue
+    }));
+    RUNNING.with(|r| r.set(false));
+    (result.map_err(|payload| panic_message(&*payload)), executed)
+}
+
+/// The message a panic was started with.
+fn panic_message(payload: &dyn Any) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload.downcast_ref::<String>().cloned().unwrap_or_else(|| "unknown panic".into()),
+    }
+}
+
+/// Tell what kind of fault a panic in the machine was. The machine panics when its stack pointer
+/// goes below zero or above the largest word, leaving it at the end it was going past.
+fn panicked(m: &Machine, message: String) -> RunOutcome {
+    let sp = m.read_addr(Address::RegAbs(Register::SP));
+    if sp == 0 || sp == u64::MAX {
+        fault(format!("Stack has overrun available memory! SP = {} ({})", sp, message))
+    } else {
+        RunOutcome::Fault(format!("The machine panicked: {}", message))
+    }
+}
+
+/// Tell what kind of fault the machine had. The machine only describes its faults, so they are
+/// told apart by their descriptions.
+fn fault(description: String) -> RunOutcome {
+    const OUT_OF_BOUNDS: [&str; 4] = ["Tried to write out of available memory", "Stack has overrun", "Attempt to jump to", "IP beyond program length"];
+    if description == "Illegal instruction encountered." {
+        RunOutcome::Illegal
+    } else if OUT_OF_BOUNDS.iter().any(|p| description.starts_with(p)) {
+        RunOutcome::OutOfBounds(description)
+    } else {
+        RunOutcome::Fault(description)
+    }
+}
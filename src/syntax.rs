@@ -0,0 +1,192 @@
+use std::ops::Range;
+use mlem::Program;
+use lex::{self, lex_line_with_comment};
+use parse::{assemble_source, label_definition, parse_program, Assembly, Diagnostic, Options};
+
+/// A piece of source text that means something (a label, name, operand or comment), with the
+/// whitespace before it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Token<'a> {
+    /// The text of the token itself.
+    pub text: &'a str,
+    /// The byte offset of the token in the whole source.
+    pub offset: usize,
+    /// The whitespace between the previous token (or the start of the line) and this one.
+    pub leading: &'a str,
+}
+
+impl<'a> Token<'a> {
+    /// The bytes of the whole source this token covers, not counting the whitespace before it.
+    pub fn span(&self) -> Range<usize> {
+        self.offset..self.offset + self.text.len()
+    }
+}
+
+/// What a line of source holds. A line holding labels and a statement has the kind of the statement.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineKind {
+    /// Nothing but whitespace.
+    Blank,
+    /// Only a comment.
+    Comment,
+    /// Only label definitions (and maybe a comment).
+    Label,
+    /// An instruction, pseudo-instruction, or macro invocation.
+    Instruction,
+    /// A directive, like `.equ`.
+    Directive,
+}
+
+/// A line of source, split into tokens without losing any of its text.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LineNode<'a> {
+    pub kind: LineKind,
+    /// The bytes of the whole source this line covers, including its line ending.
+    pub span: Range<usize>,
+    /// The label definitions at the start of the line, with their colons.
+    pub labels: Vec<Token<'a>>,
+    /// The statement on the line: the name of the instruction, directive, or macro, then its operands.
+    pub statement: Vec<Token<'a>>,
+    /// The comment on the line, starting with its semicolon.
+    pub comment: Option<Token<'a>>,
+    /// Everything after the last token, up to the line ending; usually whitespace.
+    pub trailing: &'a str,
+    /// The line ending: `"\n"`, `"\r\n"`, or nothing, for the last line of source without one.
+    pub newline: &'a str,
+}
+
+impl<'a> LineNode<'a> {
+    /// Every token in the line, in order.
+    pub fn tokens(&self) -> Vec<&Token<'a>> {
+        self.labels.iter().chain(&self.statement).chain(&self.comment).collect()
+    }
+
+    /// The text of the line, exactly as it was in the source.
+    pub fn text(&self) -> String {
+        let mut text: String = self.tokens().iter().map(|t| format!("{}{}", t.leading, t.text)).collect();
+        text += self.trailing;
+        text += self.newline;
+        text
+    }
+}
+
+/// The source of a program, as a lossless tree of lines and tokens. Nothing is thrown away, so the
+/// original text can be rebuilt exactly, as can the program it assembles to; tools like formatters
+/// and editors can work on the tree, and still have every comment and space of the source.
+///
+/// ```
+/// use mlem_asm::parse_program;
+/// use mlem_asm::syntax::{SyntaxTree, LineKind};
+/// let source = "loop:\tsub R0 1 ; count down\r\n  jnz loop R0\n\nhalt";
+/// let tree = SyntaxTree::new(source);
+/// assert_eq!(tree.lines[0].kind, LineKind::Instruction);
+/// assert_eq!(tree.lines[0].statement[1].text, "R0");
+/// assert_eq!(tree.lines[0].statement[1].span(), 10..12);
+/// assert_eq!(tree.lines[0].comment.unwrap().text, "; count down");
+/// assert_eq!(tree.lines[2].kind, LineKind::Blank);
+/// assert_eq!(tree.text(), source);
+/// assert_eq!(tree.program().unwrap(), parse_program(source).unwrap());
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SyntaxTree<'a> {
+    pub lines: Vec<LineNode<'a>>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// Build the tree of some source.
+    pub fn new(source: &'a str) -> Self {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for line in source.split_inclusive('\n') {
+            lines.push(parse_line_node(line, start));
+            start += line.len();
+        }
+        SyntaxTree { lines }
+    }
+
+    /// The source the tree was built from, exactly as it was.
+    pub fn text(&self) -> String {
+        self.lines.iter().map(|l| l.text()).collect()
+    }
+
+    /// Assemble the source the tree was built from, as `parse_program` does.
+    pub fn program(&self) -> Result<Program, Vec<Diagnostic>> {
+        parse_program(&self.text())
+    }
+
+    /// Assemble the source the tree was built from, as `assemble_source` does.
+    pub fn assemble(&self, file: &str, options: &Options) -> Result<Assembly, Vec<Diagnostic>> {
+        assemble_source(&self.text(), file, options)
+    }
+}
+
+/// Build the node for a line of source, which starts at `start` in the whole source.
+fn parse_line_node<'a>(line: &'a str, start: usize) -> LineNode<'a> {
+    let newline_length = if line.ends_with("\r\n") { 2 } else if line.ends_with('\n') { 1 } else { 0 };
+    let (content, newline) = line.split_at(line.len() - newline_length);
+    let (pieces, comment) = lex_line_with_comment(content);
+    // Each token takes the whitespace since the end of the one before it.
+    let mut end = 0;
+    let mut token = |text: &'a str| {
+        let offset = lex::offset(content, text);
+        let leading = &content[end..offset];
+        end = offset + text.len();
+        Token { text, offset: start + offset, leading }
+    };
+    let label_count = pieces.iter().take_while(|p| label_definition(p).is_some()).count();
+    let labels: Vec<Token> = pieces[..label_count].iter().map(|p| token(p)).collect();
+    let statement: Vec<Token> = pieces[label_count..].iter().map(|p| token(p)).collect();
+    // The comment starts at its semicolon, just before the text `lex_line_with_comment` gives.
+    let comment = comment.map(|c| {
+        let semicolon = lex::offset(content, c) - 1;
+        token(&content[semicolon..semicolon + 1 + c.len()])
+    });
+    let kind = match (statement.first(), labels.is_empty(), comment.is_some()) {
+        (Some(name), _, _) if name.text.starts_with('.') => LineKind::Directive,
+        (Some(_), _, _) => LineKind::Instruction,
+        (None, false, _) => LineKind::Label,
+        (None, true, true) => LineKind::Comment,
+        (None, true, false) => LineKind::Blank,
+    };
+    LineNode { kind, span: start..start + line.len(), labels, statement, comment, trailing: &content[end..], newline }
+}
+
+#[cfg(test)]
+mod test_syntax {
+    use super::*;
+
+    #[test]
+    fn test_lossless() {
+        let sources = [
+            "",
+            "\n\n",
+            include_str!("../test.asm"),
+            "  a: b:\tmove ';' R0;;\t \r\nprint \"x; y\"  R1   \n\t; only a comment\r\n.equ X (1 + 2)",
+            // Carriage returns in the middle of a line end what the lexer looks at, but are kept.
+            "noop\r; after\n move 1 R0",
+        ];
+        for source in sources.iter() {
+            let tree = SyntaxTree::new(source);
+            assert_eq!(&tree.text(), source);
+            for line in &tree.lines {
+                assert_eq!(&source[line.span.clone()], line.text());
+                for token in line.tokens() {
+                    assert_eq!(&source[token.span()], token.text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_nodes() {
+        let tree = SyntaxTree::new("  a: b:\tmove ';' R0;;\t \r\n.EQU X 1\nend: ; done\n");
+        let kinds: Vec<LineKind> = tree.lines.iter().map(|l| l.kind).collect();
+        assert_eq!(kinds, [LineKind::Instruction, LineKind::Directive, LineKind::Label]);
+        let line = &tree.lines[0];
+        let texts: Vec<(&str, &str)> = line.tokens().iter().map(|t| (t.leading, t.text)).collect();
+        assert_eq!(texts, [("  ", "a:"), (" ", "b:"), ("\t", "move"), (" ", "';'"), (" ", "R0"), ("", ";;\t ")]);
+        assert_eq!((line.trailing, line.newline), ("", "\r\n"));
+        assert_eq!(tree.lines[2].span, 34..46);
+        assert_eq!(tree.lines[2].comment.unwrap().offset, 39);
+    }
+}
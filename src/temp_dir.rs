@@ -0,0 +1,34 @@
+// Also used by the command line tests, in tests/cli.rs.
+use std::{env, fs, process};
+use std::path::{Path, PathBuf};
+
+/// A fresh temporary directory for a test, removed along with everything in it when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Make a directory named after the test using it.
+    pub fn new(test_name: &str) -> Self {
+        let path = env::temp_dir().join(format!("mlem-asm-{}-{}", test_name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write a file into the directory, making any directories it's in, and give its path.
+    pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.0.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -16,3 +16,126 @@ fn test_program_to_writer() {
     program_to_writer(&prog, &mut buffer).unwrap();
     assert!(buffer.get_ref() == &expected_cbor, "Program resulted in: {:?} not: {:?}", buffer.get_ref(), expected_cbor);
 }
+
+#[test]
+fn test_example_program_matches_binary() {
+    use std::io::Cursor;
+    let mut buffer = Cursor::new(Vec::<u8>::new());
+    let prog = super::parse::parse_program(include_str!("../test.asm")).unwrap();
+    program_to_writer(&prog, &mut buffer).unwrap();
+    assert_eq!(&buffer.get_ref()[..], &include_bytes!("../test.bin")[..]);
+}
+
+#[test]
+fn test_disassemble_round_trip() {
+    use std::io::Cursor;
+    use {disassemble, program_from_reader, parse_program, Instruction, Address, Register};
+    use format::FormatOptions;
+    let binary = &include_bytes!("../test.bin")[..];
+    let program = program_from_reader(&mut Cursor::new(binary)).unwrap();
+    let text = disassemble(&program, &FormatOptions::default(), false).unwrap();
+    assert_eq!(text.lines().nth(3), Some("output R0"));
+    let mut buffer = Cursor::new(Vec::<u8>::new());
+    program_to_writer(&parse_program(&text).unwrap(), &mut buffer).unwrap();
+    assert_eq!(&buffer.get_ref()[..], binary);
+
+    // Jumps outside the program are allowed, so that they assemble as they were.
+    let program = vec![Instruction::Jump(Address::Literal(9)), Instruction::Pop(Address::MemAbs(0x10))];
+    let text = disassemble(&program, &FormatOptions::default(), false).unwrap();
+    assert_eq!(text, ".allow jump-out-of-range\njump 9\npop *16\n");
+    assert_eq!(parse_program(&text), Ok(program));
+
+    // Literals can't be written to in mlasm.
+    let program = vec![Instruction::NoOp, Instruction::Input(Address::Literal(3)), Instruction::Push(Address::RegAbs(Register::BP))];
+    assert_eq!(disassemble(&program, &FormatOptions::default(), false).unwrap_err().index, 1);
+}
+
+#[test]
+fn test_listing() {
+    use {assemble_source, listing_to_writer, Options};
+    let source = ".equ TEN 10\n.macro twice x\nadd \\x \\x\n.endm\nmove TEN R0\ntwice R0\nprint \"ab\" R1\nhalt\n";
+    let mut options = Options::default();
+    options.define("DEBUG").unwrap();
+    let assembly = assemble_source(source, "macro.asm", &options).unwrap();
+    let mut listing = Vec::new();
+    listing_to_writer(&assembly, &mut listing).unwrap();
+    let listing = String::from_utf8(listing).unwrap();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], "macro.asm:1                                  .equ TEN 10  [TEN = 10]");
+    assert_eq!(lines[1], "macro.asm:5   0  83 02 82 03 0a 82 00 00     move TEN R0  [TEN = 10]");
+    assert_eq!(lines[2], "macro.asm:6+  1  83 05 82 00 00 82 00 00     add R0 R0");
+    assert_eq!(&lines[3..8], ["macro.asm:7   2  83 02 82 03 18 61 82 00 01  print \"ab\" R1",
+                              "              3  82 03 82 00 01",
+                              "              4  83 02 82 03 18 62 82 00 01",
+                              "              5  82 03 82 00 01",
+                              "macro.asm:8   6  0c                          halt"]);
+    assert_eq!(&lines[8..], ["", "Symbols:", "  DEBUG  constant  1 (0x1)  <command line>", "  TEN    constant  10 (0xa)  macro.asm:1"]);
+
+    // The encodings in the listing are those of the whole program.
+    let mut buffer = Vec::new();
+    program_to_writer(&assembly.program, &mut buffer).unwrap();
+    let listed = lines[..8].iter().map(|l| l[17..].split("  ").next().unwrap()).collect::<Vec<_>>().join(" ");
+    let encoded: Vec<String> = buffer[4..].iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(listed.split_whitespace().collect::<Vec<_>>(), encoded);
+}
+
+#[test]
+fn test_debug_info() {
+    use {assemble_source, debug_info_from_reader, debug_info_to_writer, DebugInfo, Options, SourceLocation};
+    let source = ".macro twice x\nadd \\x \\x\n.endm\nstart: first: move 1 R0\n  twice R0\nend: halt\n";
+    let assembly = assemble_source(source, "macro.asm", &Options::default()).unwrap();
+    let info = DebugInfo::new(&assembly);
+    let location = |line, column, label: &str| SourceLocation { file: "macro.asm".into(), line, column, label: Some(label.into()) };
+    // Instructions from a macro are at its invocation; of two labels on one instruction, the first is used.
+    assert_eq!(info.instructions, [location(3, 14, "start"), location(4, 2, "start"), location(5, 5, "end")]);
+    assert_eq!(info.lookup(1).unwrap().to_string(), "macro.asm:5:3 (in start)");
+
+    let mut buffer = Vec::new();
+    debug_info_to_writer(&info, &mut buffer).unwrap();
+    assert_eq!(&buffer[..3], [0xd9, 0xd9, 0xf7]);
+    assert_eq!(debug_info_from_reader(&mut &buffer[..]).unwrap(), info);
+}
+
+#[test]
+fn test_run_program() {
+    use std::time::Duration;
+    use {parse_program, run_program, RunOptions, RunOutcome};
+    let run = |source: &str, options: &RunOptions| {
+        let mut output = Vec::new();
+        let (outcome, cycles) = run_program(parse_program(source).unwrap(), &mut &[0, 0, 0, 0, 0, 0, 0, 65][..], &mut output, options);
+        (outcome, cycles, output)
+    };
+    let defaults = RunOptions::default();
+    assert_eq!(run("input R0\nadd R0 1\noutput R0\nhalt", &defaults), (RunOutcome::Halt, 4, vec![0, 0, 0, 0, 0, 0, 0, 66]));
+    // Writing to the first word of memory, and the last, works.
+    assert_eq!(run("move 1 *R0\nmove 65534 R0\nmove 1 *R0\nhalt", &defaults).0, RunOutcome::Halt);
+    let small = RunOptions { memory: 10, ..RunOptions::default() };
+    assert_eq!(run("move 100 R0\nmove 1 *R0\nhalt", &small).0, RunOutcome::OutOfBounds("Tried to write out of available memory: 100".into()));
+    let huge = RunOptions { memory: usize::MAX / 2, ..RunOptions::default() };
+    assert_eq!(run("halt", &huge), (RunOutcome::Fault(format!("Could not allocate {} words of memory.", usize::MAX / 2)), 0, vec![]));
+    let huge = RunOptions { memory: usize::MAX, ..RunOptions::default() };
+    assert_eq!(run("halt", &huge).0, RunOutcome::Fault(format!("Could not allocate {} words of memory.", usize::MAX)));
+    // Ways the machine itself would break become faults too.
+    assert_eq!(run_program(Vec::new(), &mut ::std::io::empty(), &mut ::std::io::sink(), &defaults),
+               (RunOutcome::OutOfBounds("IP beyond program length. IP = 0, length = 0".into()), 0));
+    assert_eq!(run("; just a comment", &defaults).0, RunOutcome::OutOfBounds("IP beyond program length. IP = 0, length = 0".into()));
+    let underflow = RunOutcome::OutOfBounds("Stack has overrun available memory! SP = 0 (attempt to subtract with overflow)".into());
+    assert_eq!(run("move 0 RSP\npush 1\nhalt", &defaults), (underflow.clone(), 2, vec![]));
+    let tiny = RunOptions { memory: 1, ..RunOptions::default() };
+    assert_eq!(run("push 1\nhalt", &tiny), (underflow, 1, vec![]));
+    assert_eq!(run("move 18446744073709551615 RBP\nmove RBP RSP\npop R0\nhalt", &defaults).0,
+               RunOutcome::OutOfBounds("Stack has overrun available memory! SP = 18446744073709551615 (attempt to add with overflow)".into()));
+    assert_eq!(run("noop\nillegal", &defaults), (RunOutcome::Illegal, 2, vec![]));
+    assert_eq!(run("move 1 R0", &defaults).0, RunOutcome::OutOfBounds("IP beyond program length. IP = 1, length = 1".into()));
+    assert_eq!(run("input R0\ninput R0\nhalt", &defaults).0,
+               RunOutcome::Fault("Failed to read on input instruction: failed to fill whole buffer.".into()));
+
+    // A budget counts every instruction, including the one that halts.
+    let budget = |max| RunOptions { max_cycles: Some(max), ..RunOptions::default() };
+    assert_eq!(run("noop\nnoop\nhalt", &budget(3)).0, RunOutcome::Halt);
+    assert_eq!(run("noop\nnoop\nhalt", &budget(2)), (RunOutcome::CycleLimit, 2, vec![]));
+    assert_eq!(run("loop: jump loop", &budget(25_000)).1, 25_000);
+
+    let timeout = RunOptions { timeout: Some(Duration::from_millis(50)), ..RunOptions::default() };
+    assert_eq!(run("loop: jump loop", &timeout).0, RunOutcome::Timeout);
+}
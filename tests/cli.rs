@@ -0,0 +1,139 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+#[path = "../src/temp_dir.rs"]
+mod temp_dir;
+use temp_dir::TempDir;
+
+/// Run the command line program with the given arguments.
+fn mlem_asm(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mlem-asm")).args(args).output().unwrap()
+}
+
+#[test]
+fn test_help_as_option_value() {
+    let dir = TempDir::new("help");
+    let program = dir.write("echo.asm", "input R0\noutput R0\nhalt\n");
+    let program = program.to_str().unwrap();
+    // Eight bytes make one word of input, which the program writes back out.
+    let run = mlem_asm(&["run", program, "--quiet", "--input-string", "-h------"]);
+    assert_eq!(run.status.code(), Some(0));
+    assert_eq!(run.stdout, b"-h------");
+    assert_eq!(mlem_asm(&["run", program, "--help"]).status.code(), Some(0));
+    assert!(String::from_utf8(mlem_asm(&["--version"]).stdout).unwrap().starts_with("mlem-asm "));
+}
+
+#[test]
+fn test_memory_limit() {
+    let dir = TempDir::new("memory");
+    let program = dir.write("halt.asm", "halt\n");
+    let program = program.to_str().unwrap();
+    for memory in &["18446744073709551615", "4000000000", "16777217", "0"] {
+        let run = mlem_asm(&["run", program, "--memory", memory]);
+        assert_eq!(run.status.code(), Some(2), "--memory {}", memory);
+        assert!(String::from_utf8(run.stderr).unwrap().starts_with("--memory "), "--memory {}", memory);
+    }
+    assert_eq!(mlem_asm(&["run", program, "--quiet", "--memory", "16777216"]).status.code(), Some(0));
+}
+
+#[test]
+fn test_run_io() {
+    let source = "input R0\nadd R0 1\noutput R0\nhalt\n";
+    let dir = TempDir::new("run-io");
+    let program = dir.write("increment.asm", source);
+    let output = dir.path().join("output");
+    let run = mlem_asm(&["run", program.to_str().unwrap(), "--quiet", "--input-string", "AAAAAAAA"]);
+    assert_eq!(run.status.code(), Some(0));
+    assert_eq!(run.stdout, b"AAAAAAAB");
+    assert_eq!(fs::read_to_string(&program).unwrap(), source);
+
+    let run = mlem_asm(&["run", program.to_str().unwrap(), "--quiet", "--input-string", "AAAAAAAA", "-o", output.to_str().unwrap()]);
+    assert_eq!(run.status.code(), Some(0));
+    assert_eq!(run.stdout, b"");
+    assert_eq!(fs::read(&output).unwrap(), b"AAAAAAAB");
+    assert_eq!(fs::read_to_string(&program).unwrap(), source);
+}
+
+#[test]
+fn test_run_exit_codes() {
+    let dir = TempDir::new("exit-codes");
+    let run = |name: &str, source: &str, options: &[&str]| {
+        let program = dir.write(name, source);
+        let mut args = vec!["run", program.to_str().unwrap()];
+        args.extend_from_slice(options);
+        mlem_asm(&args)
+    };
+    let halt = run("halt.asm", "halt\n", &[]);
+    assert_eq!(halt.status.code(), Some(0));
+    assert!(!halt.stderr.is_empty());
+    assert_eq!(run("loop.asm", "loop: jump loop\n", &["--max-cycles", "1000"]).status.code(), Some(5));
+    assert_eq!(run("loop.asm", "loop: jump loop\n", &["--timeout", "0.1"]).status.code(), Some(6));
+    assert_eq!(run("illegal.asm", "illegal\n", &[]).status.code(), Some(7));
+    assert_eq!(run("bounds.asm", "move 100 R0\nmove 1 *R0\nhalt\n", &["--memory", "10"]).status.code(), Some(8));
+    // Programs the machine can't run at all end the same way, rather than crashing.
+    assert_eq!(run("empty.asm", "; nothing to run\n", &[]).status.code(), Some(8));
+    assert_eq!(run("underflow.asm", "move 0 RSP\npush 1\nhalt\n", &[]).status.code(), Some(8));
+    assert_eq!(run("push.asm", "push 1\nhalt\n", &["--memory", "1"]).status.code(), Some(8));
+
+    // Quietly, how the program ended isn't written; these programs have nothing to warn about either.
+    let quiet = run("halt.asm", "halt\n", &["--quiet"]);
+    assert_eq!((quiet.status.code(), quiet.stderr), (Some(0), vec![]));
+    let quiet = run("bounds.asm", "move 100 R0\nmove 1 *R0\nhalt\n", &["--memory", "10", "--quiet"]);
+    assert_eq!((quiet.status.code(), quiet.stderr), (Some(8), vec![]));
+}
+
+#[test]
+fn test_format_check() {
+    let dir = TempDir::new("fmt-check");
+    let formatted = dir.write("formatted.asm", "move 1 R0\nmove (1 + 2 R0 ; z  \nhalt\n");
+    let unformatted = dir.write("unformatted.asm", "MOVE 1 r0\nmove (1 + 2 R0 ; z  \n  halt\n");
+    let check = |path: &PathBuf| mlem_asm(&["fmt", "--check", path.to_str().unwrap()]);
+    assert_eq!(check(&formatted).status.code(), Some(0));
+    let unformatted_check = check(&unformatted);
+    assert_eq!(unformatted_check.status.code(), Some(1));
+    assert_eq!(String::from_utf8(unformatted_check.stdout).unwrap(), format!("{} is not formatted.\n", unformatted.display()));
+    // Formatting once is enough.
+    assert_eq!(mlem_asm(&["fmt", unformatted.to_str().unwrap()]).status.code(), Some(0));
+    assert_eq!(check(&unformatted).status.code(), Some(0));
+    assert_eq!(fs::read(&unformatted).unwrap(), fs::read(&formatted).unwrap());
+}
+
+#[test]
+fn test_run_assembled() {
+    let dir = TempDir::new("run-assembled");
+    let source = dir.write("test.asm", include_str!("../test.asm"));
+    let assembled = dir.path().join("test.bin");
+    let untagged = dir.path().join("untagged.bin");
+    let assemble = mlem_asm(&["assemble", source.to_str().unwrap(), "-o", assembled.to_str().unwrap()]);
+    assert_eq!(assemble.status.code(), Some(0));
+    // The example prints the characters from space to tilde, and one more, each in a word.
+    let expected: Vec<u8> = (32..128).flat_map(|c| vec![0, 0, 0, 0, 0, 0, 0, c]).collect();
+    let run = mlem_asm(&["run", assembled.to_str().unwrap(), "--quiet"]);
+    assert_eq!((run.status.code(), run.stdout), (Some(0), expected.clone()));
+
+    // Without the tag that marks it as assembled, the program is only run as one if asked to.
+    let bytes = fs::read(&assembled).unwrap();
+    assert_eq!(bytes[..3], [0xd9, 0xd9, 0xf7]);
+    fs::write(&untagged, &bytes[3..]).unwrap();
+    let run = mlem_asm(&["run", untagged.to_str().unwrap(), "--quiet", "--binary"]);
+    assert_eq!((run.status.code(), run.stdout), (Some(0), expected));
+    // Otherwise it's read as source, which it can't be.
+    let run = mlem_asm(&["run", untagged.to_str().unwrap(), "--quiet"]);
+    assert_eq!((run.status.code(), run.stdout), (Some(3), vec![]));
+    assert!(String::from_utf8(run.stderr).unwrap().contains("stream did not contain valid UTF-8"));
+}
+
+#[test]
+fn test_command_line_diagnostics() {
+    // Diagnostics about definitions on the command line have no source to show, even if there
+    // happens to be a file with the name they're given.
+    let dir = TempDir::new("command-line");
+    dir.write("halt.asm", "halt\n");
+    dir.write("<command line>", "not the command line\n");
+    let check = Command::new(env!("CARGO_BIN_EXE_mlem-asm")).args(["check", "-D", "A", "-D", "A", "halt.asm"])
+        .current_dir(dir.path()).output().unwrap();
+    assert_eq!(check.status.code(), Some(1));
+    assert_eq!(String::from_utf8(check.stderr).unwrap(),
+               "error: Duplicate symbol: A (first defined on line 1)\n --> <command line>:1:1\n\nCould not assemble program due to 1 error.\n");
+}